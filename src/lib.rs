@@ -4,9 +4,15 @@ std::io::Write implementation.
 
 ### Features
 
-- A simple, bare-minimum API that panics when writing invalid XML.
-- Non-allocating API. All methods are accepting either `fmt::Display` or `fmt::Arguments`.
+- A configurable API that panics when it's misused, e.g. when writing attributes
+  after children, and returns an error for values that can't be written.
+- Streaming API. All methods are accepting either `fmt::Display` or `fmt::Arguments`,
+  which are escaped while being written. The names of open elements are stored
+  to write the closing tags.
 - Nodes auto-closing.
+- `no_std` support. Disable the default `std` feature to write into a `core::fmt::Write`
+  implementation instead. Only `alloc` is required, which needs Rust 1.36+.
+  Methods return `io::Result` with `std` and `Result<T, fmt::Error>` without it.
 
 ### Example
 
@@ -14,6 +20,8 @@ std::io::Write implementation.
 use xmlwriter::*;
 use std::io;
 
+# #[cfg(not(feature = "std"))] fn main() {}
+# #[cfg(feature = "std")]
 fn main() -> io::Result<()> {
     let opt = Options {
         use_single_quote: true,
@@ -44,12 +52,67 @@ fn main() -> io::Result<()> {
 ```
 */
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 #![warn(missing_copy_implementations)]
 
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+#[cfg(not(feature = "std"))]
+use core::fmt::{self, Display, Write};
+
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
 use std::fmt::{self, Display, Write as FmtWrite};
+#[cfg(feature = "std")]
 use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// With `std`, the writer is an `io::Write` and errors are `io::Error`.
+// Without it, the writer is a `fmt::Write` and errors are `fmt::Error`.
+// Not exported, since `use xmlwriter::*` would shadow the prelude `Result`.
+// Documented in the crate features list instead.
+#[cfg(feature = "std")]
+type Result<T> = io::Result<T>;
+#[cfg(not(feature = "std"))]
+type Result<T> = core::result::Result<T, fmt::Error>;
+
+// An error for when a user-provided formatting closure fails.
+#[cfg(feature = "std")]
+fn formatter_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "formatter error")
+}
+
+#[cfg(not(feature = "std"))]
+fn formatter_error() -> fmt::Error {
+    fmt::Error
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+// An error for when the provided data cannot be written.
+#[cfg(feature = "std")]
+fn invalid_data_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(not(feature = "std"))]
+fn invalid_data_error(_: &'static str) -> fmt::Error {
+    fmt::Error
+}
 
 /// An XML node indention.
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -57,13 +120,97 @@ pub enum Indent {
     /// Disable indention and new lines.
     None,
     /// Indent with spaces. Preferred range is 0..4.
+    ///
+    /// `Spaces(0)` still writes new lines, but without any indention,
+    /// unlike `None`, which writes no new lines at all.
     Spaces(u8),
     /// Indent with tabs.
     Tabs,
 }
 
+impl Indent {
+    /// The default indention, 4 spaces.
+    pub const DEFAULT: Indent = Indent::Spaces(4);
+
+    /// Creates an indention with `n` spaces.
+    #[inline]
+    pub fn spaces(n: u8) -> Self {
+        Indent::Spaces(n)
+    }
+
+    /// Returns the number of characters in a single indention level.
+    ///
+    /// A tab is counted as one character. Its width in columns is up to the caller.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::Indent;
+    ///
+    /// assert_eq!(Indent::None.width(), 0);
+    /// assert_eq!(Indent::Spaces(2).width(), 2);
+    /// assert_eq!(Indent::Tabs.width(), 1);
+    /// ```
+    #[inline]
+    pub fn width(self) -> usize {
+        match self {
+            Indent::None => 0,
+            Indent::Spaces(n) => n as usize,
+            Indent::Tabs => 1,
+        }
+    }
+}
+
+/// A `>` escaping mode for text nodes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextGtEscaping {
+    /// Escape all `>`.
+    Always,
+    /// Escape `>` only when it's preceded by `]]`, as required by the XML spec.
+    OnlyAfterBrackets,
+    /// Never escape `>`.
+    ///
+    /// Produces invalid XML when the text contains `]]>`.
+    Never,
+}
+
+/// An XML version.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XmlVersion {
+    /// XML 1.0.
+    ///
+    /// Control characters, except tab, new line and carriage return,
+    /// are not allowed in text and attribute values and result in an `InvalidData` error.
+    Xml10,
+    /// XML 1.1.
+    ///
+    /// Control characters, except tab, new line and carriage return,
+    /// are written as character references, like `&#x1B;`.
+    /// `U+0000` is still not allowed and results in an `InvalidData` error.
+    Xml11,
+}
+
+/// A line ending used for new lines written by the writer itself.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl LineEnding {
+    #[inline]
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
 /// An XML writing options.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy)]
 pub struct Options {
     /// Use single quote marks instead of double quote.
     ///
@@ -152,301 +299,5458 @@ pub struct Options {
     ///
     /// Default: enabled
     pub enable_self_closing: bool,
-}
-
-impl Default for Options {
-    #[inline]
-    fn default() -> Self {
-        Options {
-            use_single_quote: false,
-            indent: Indent::Spaces(4),
-            attributes_indent: Indent::None,
-            enable_self_closing: true,
-        }
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, Debug)]
-enum State {
-    Empty,
-    Document,
-    Attributes,
-    CData,
-}
-
-#[derive(Clone, Debug)]
-struct DepthData<'a> {
-    element_name: Option<&'a str>,
-    has_children: bool,
-}
-
-// This wrapper writer is so that we can make sure formatted strings are properly escaped too,
-// as we don't have access to the formatting stuff without a fmt::Write implementation, so
-// we provide it by wrapping the writer given to us while escaping appropriately any string to
-// be written, depending on the type of node we're writing.
-#[derive(Clone, Debug)]
-struct FmtWriter<W: Write> {
-    writer: W,
-    error_kind: Option<io::ErrorKind>,
-    // Set to None once the text is written, as a way to make sure the code
-    // sets the proper escaping type before using the fmt_writer.write_str().
-    escape: Option<Escape>,
-    // Same as for Options, but kept available for write_escaped()
-    use_single_quote: bool,
-}
-
-impl<W: Write> FmtWriter<W> {
-    fn take_err(&mut self) -> io::Error {
-        let error_kind = self
-            .error_kind
-            .expect("there must have been an error before calling take_err()!");
-        // This avoids forgetting to set it to the appropriate value when calling write_fmt().
-        // We can't do it in FmtWriter's write_str(), since with a real format string the method
-        // will be called several times so it'll fail in the expect() below as we'll have set
-        // self.escape back to None.
-        self.escape = None;
-        // Make sure we can detect if take_err() is called without having an error that happened beforehand
-        self.error_kind = None;
-
-        // There's just no way of properly copying the io::Error (no Copy or Clone available), so
-        // we have no choice to create a new one, which likely loses the backtrace up to this point.
-        io::Error::from(error_kind)
-    }
-
-    fn write_escaped(&mut self, s: &str, escape_quotes: bool) -> io::Result<()> {
-        let mut part_start_pos = 0;
-        for (byte_pos, byte) in s.bytes().enumerate() {
-            let escaped_char: Option<&[u8]> = match byte {
-                b'&' => Some(b"&amp;"),
-                b'>' => Some(b"&gt;"),
-                b'<' => Some(b"&lt;"),
-                b'"' if escape_quotes && !self.use_single_quote => Some(b"&quot;"),
-                b'\'' if escape_quotes && self.use_single_quote => Some(b"&apos;"),
-                _ => None,
-            };
-            if let Some(escaped_char) = escaped_char {
-                // We have a character to escape, so write the previous part and the escaped character
-                self.writer
-                    .write_all(s[part_start_pos..byte_pos].as_bytes())?;
-                self.writer.write_all(escaped_char)?;
-                // +1 skips the escaped character from part, for afterwards
-                part_start_pos = byte_pos + 1;
-            }
-            // There's nothing to be done if the character doesn't need to be escaped, as we'll either
-            // wait until we get an escapable character, or wait until the end of the string where we'll
-            // just write out the rest of the string.
-        }
-        // Write the rest of the string which needs no escaping
-        self.writer.write_all(s[part_start_pos..].as_bytes())
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, Debug)]
-enum Escape {
-    Comment,
-    AttributeValue,
-    Text,
-    CData,
-}
-
-impl<W: Write> fmt::Write for FmtWriter<W> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        let error = match self
-            .escape
-            .expect("You must have set self.escape to Some(…) before using the formatter!")
-        {
-            Escape::AttributeValue => self.write_escaped(s, true),
-            Escape::Text => self.write_escaped(s, false),
-            // We don't bother escaping double hyphen (--) in comment as it's
-            // unlikely to ever happen, and even libxml2 does not do it.
-            Escape::Comment => self.writer.write_all(s.as_bytes()),
-            Escape::CData => self.writer.write_all(s.as_bytes()),
-        };
-        if error.is_err() {
-            self.error_kind = Some(error.as_ref().unwrap_err().kind());
-            Err(fmt::Error)
-        } else {
-            Ok(())
-        }
-    }
-}
-
-/// An XML writer.
-#[derive(Clone, Debug)]
-pub struct XmlWriter<'a, W: Write> {
-    // When you control what you're writing enough that you know the bytes are already escaped or
-    // don't need escaping at all, then use fmt_writer.writer.write_all()?; directly. Otherwise,
-    // set fmt_writer.escape to the appropriate escaping type and use fmt_writer.write_fmt()?; or
-    // fmt_writer.write_str()?; if you are only printing a string directly without formatting, but
-    // still want escaping to be done.
-    fmt_writer: FmtWriter<W>,
-    state: State,
-    preserve_whitespaces: bool,
-    depth_stack: Vec<DepthData<'a>>,
-    opt: Options,
-}
-
-impl<'a, W: Write> XmlWriter<'a, W> {
-    /// Creates a new `XmlWriter`, writing data in the writer.
-    #[inline]
-    pub fn new(writer: W, opt: Options) -> Self {
-        XmlWriter {
-            fmt_writer: FmtWriter {
-                writer,
-                error_kind: None,
-                escape: None,
-                use_single_quote: opt.use_single_quote,
-            },
-            state: State::Empty,
-            preserve_whitespaces: false,
-            depth_stack: Vec::new(),
-            opt,
-        }
-    }
 
-    /// Writes an XML declaration.
+    /// Wrap text nodes at word boundaries near the specified column.
     ///
-    /// `<?xml version="1.0" encoding="UTF-8" standalone="no"?>`
+    /// Continuation lines are indented like the text node itself.
+    /// Consecutive whitespaces between words are collapsed into a single space.
     ///
-    /// # Panics
+    /// Doesn't affect CDATA and attributes and has no effect when indention is disabled
+    /// or when whitespaces are preserved.
     ///
-    /// - When called twice.
-    #[inline(never)]
-    pub fn write_declaration(&mut self) -> io::Result<()> {
-        if self.state != State::Empty {
-            panic!("declaration was already written");
-        }
-
-        // Pretend that we are writing an element.
-        self.state = State::Attributes;
-
-        // <?xml version='1.0' encoding='UTF-8' standalone='yes'?>
-        self.fmt_writer.writer.write_all(b"<?xml")?;
-        // We don't use write_all() directly so that we get quoting handling for free.
-        // However we can use the "raw" method here as we perfectly know there's no
-        // escaping needed, albeit the performance impact would be almost inexistent if
-        // we did use the regular method.
-        self.write_attribute_raw("version", |w| w.write_all(b"1.0"))?;
-        self.write_attribute_raw("encoding", |w| w.write_all(b"UTF-8"))?;
-        self.write_attribute_raw("standalone", |w| w.write_all(b"no"))?;
-        self.fmt_writer.writer.write_all(b"?>")?;
-
-        self.state = State::Document;
-
-        Ok(())
-    }
-
-    /// Writes a comment string.
-    pub fn write_comment(&mut self, text: &str) -> io::Result<()> {
-        self.write_comment_fmt(format_args!("{}", text))
-    }
-
-    /// Writes a formatted comment. Forbidden double hyphens will be escaped.
-    #[inline(never)]
-    pub fn write_comment_fmt(&mut self, fmt: fmt::Arguments) -> io::Result<()> {
-        if self.state == State::Attributes {
-            self.write_open_element()?;
-        }
-
-        if self.state != State::Empty {
-            self.write_new_line()?;
-        }
-
-        self.write_node_indent()?;
-
-        // <!--text-->
-        self.fmt_writer.writer.write_all(b"<!--")?;
-        self.fmt_writer.escape = Some(Escape::Comment);
-        self.fmt_writer
-            .write_fmt(fmt)
-            .map_err(|_| self.fmt_writer.take_err())?;
-        self.fmt_writer.writer.write_all(b"-->")?;
-
-        if self.state == State::Attributes {
-            self.depth_stack.push(DepthData {
-                element_name: None,
-                has_children: false,
-            });
-        }
-
-        self.state = State::Document;
-
-        Ok(())
-    }
-
-    /// Starts writing a new element.
+    /// # Examples
     ///
-    /// This method writes only the `<tag-name` part.
-    #[inline(never)]
-    pub fn start_element(&mut self, name: &'a str) -> io::Result<()> {
-        if self.state == State::Attributes {
-            self.write_open_element()?;
-        }
-
-        if self.state != State::Empty {
-            self.write_new_line()?;
-        }
-
-        if !self.preserve_whitespaces {
-            self.write_node_indent()?;
-        }
-
-        self.fmt_writer.writer.write_all(b"<")?;
-        self.fmt_writer.writer.write_all(name.as_bytes())?;
-
-        self.depth_stack.push(DepthData {
-            element_name: Some(name),
-            has_children: false,
-        });
-
-        self.state = State::Attributes;
+    /// `Some(20)`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>
+    ///     A very long text, indeed.
+    /// </p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>
+    ///     A very long text,
+    ///     indeed.
+    /// </p>
+    /// ```
+    ///
+    /// Default: `None`
+    pub text_wrap_width: Option<usize>,
 
-        Ok(())
-    }
+    /// The width of a tab, in columns.
+    ///
+    /// Used to calculate the line width when `Options::text_wrap_width` is set
+    /// and the indention is `Indent::Tabs`.
+    ///
+    /// Default: 4
+    pub tab_width: u8,
 
-    /// Writes an attribute.
+    /// Set how `>` is escaped in text nodes.
     ///
-    /// Any occurrence of `&<>"'` in the value will be escaped.
+    /// Attribute values are not affected.
     ///
-    /// # Panics
+    /// # Examples
     ///
-    /// - When called before `start_element()`.
-    /// - When called after `close_element()`.
+    /// `TextGtEscaping::OnlyAfterBrackets`
     ///
-    /// # Example
+    /// Before:
+    ///
+    /// ```text
+    /// <p>a&gt;b ]]&gt;</p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>a>b ]]&gt;</p>
+    /// ```
+    ///
+    /// Default: `TextGtEscaping::Always`
+    pub text_gt_escaping: TextGtEscaping,
+
+    /// Separate sibling nodes with a single space when indention is disabled.
+    ///
+    /// Has no effect when indention is enabled or when whitespaces are preserved.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <svg><rect/><rect/></svg>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <svg><rect/> <rect/></svg>
+    /// ```
+    ///
+    /// Default: disabled
+    pub space_between_siblings_when_compact: bool,
+
+    /// Close the start tag of the current element when a comment is written
+    /// right after it.
+    ///
+    /// When disabled, writing a comment while attributes can still be written
+    /// will panic instead of silently closing the attributes list.
+    ///
+    /// Default: enabled
+    pub open_element_on_comment: bool,
+
+    /// Replace characters in text nodes with named entity references.
+    ///
+    /// Each pair is a character and an entity name without `&` and `;`.
+    /// A character reference, like `#38`, can be used as a name as well.
+    /// Attribute values, comments and CDATA are not affected.
+    ///
+    /// Characters that are escaped anyway, i.e. `&<>"'`, can be mapped too,
+    /// overriding the built-in entities. Such mapping applies to attribute values as well.
+    ///
+    /// Only `&amp;`, `&lt;`, `&gt;`, `&apos;` and `&quot;` are predefined by XML,
+    /// so any other entity must be declared by the document DTD.
+    ///
+    /// # Examples
+    ///
+    /// `Some(&[('—', "mdash")])`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>A—B</p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>A&mdash;B</p>
+    /// ```
+    ///
+    /// Default: `None`
+    pub entity_map: Option<&'static [(char, &'static str)]>,
+
+    /// Enable additional checks with more specific panic messages.
+    ///
+    /// - Text at the document level, i.e. outside the root element,
+    ///   panics with `text not allowed at document level`.
+    /// - `<` in values written by `XmlWriter::write_attribute_raw_checked()`
+    ///   results in an `InvalidData` error.
+    /// - Namespace prefixes passed to `XmlWriter::start_element_ns()` must be declared
+    ///   via `XmlWriter::with_namespace()`.
+    /// - `XmlWriter::end_element()` on a writer with nothing written yet
+    ///   panics with `end_element called before any element was started`.
+    /// - Invalid values passed to `XmlWriter::write_xml_lang()` and `XmlWriter::write_xml_base()`
+    ///   result in an `InvalidData` error.
+    ///
+    /// Default: disabled
+    pub strict: bool,
+
+    /// Remove spaces and tabs at the end of each line.
+    ///
+    /// Applies to all output, including text nodes, so trailing whitespace
+    /// in a multiline text will be removed as well.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>
+    ///     ␣␣␣␣
+    /// </p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>
+    ///
+    /// </p>
+    /// ```
+    ///
+    /// Default: disabled
+    pub trim_trailing_whitespace: bool,
+
+    /// Keep text on the same line as the start tag, when it's the first child of an element.
+    ///
+    /// The end tag stays on the same line too, unless something else was written on a new line.
+    /// Has no effect when `indent` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <head>
+    ///     <title>
+    ///         Hello
+    ///     </title>
+    /// </head>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <head>
+    ///     <title>Hello</title>
+    /// </head>
+    /// ```
+    ///
+    /// Default: disabled
+    pub inline_text: bool,
+
+    /// Indent the root element start tag.
+    ///
+    /// When disabled, the root element start tag is always written at the start of a line,
+    /// even when `XmlWriter::set_base_depth()` is used, while everything else is indented.
+    /// Useful when the output is inserted into an already indented line.
+    ///
+    /// # Examples
+    ///
+    /// With a base depth of 1.
+    ///
+    /// Before:
+    ///
+    /// ```text
+    ///     <svg>
+    ///         <rect/>
+    ///     </svg>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <svg>
+    ///         <rect/>
+    ///     </svg>
+    /// ```
+    ///
+    /// Default: enabled
+    pub indent_root: bool,
+
+    /// Write an attribute as a child element instead, when the predicate returns `true`.
+    ///
+    /// The predicate is called with the attribute name and value by
+    /// `XmlWriter::write_attribute()` and `XmlWriter::write_attribute_fmt()`.
+    /// Other attribute methods are not affected.
+    ///
+    /// Such child elements are written right after the start tag, once it's closed.
+    /// Note that values have to be formatted into a temporary string to be checked.
+    ///
+    /// # Examples
+    ///
+    /// `Some(|_, value| value.len() > 10)`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <item id="1" description="A long description"/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <item id="1">
+    ///     <description>
+    ///         A long description
+    ///     </description>
+    /// </item>
+    /// ```
+    ///
+    /// Default: `None`
+    pub attribute_to_element_if: Option<fn(&str, &str) -> bool>,
+
+    /// The XML version.
+    ///
+    /// Affects the version written by `XmlWriter::write_declaration()`
+    /// and the handling of control characters in text and attribute values.
+    ///
+    /// # Examples
+    ///
+    /// `XmlVersion::Xml11`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <?xml version="1.0" encoding="UTF-8" standalone="no"?>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <?xml version="1.1" encoding="UTF-8" standalone="no"?>
+    /// ```
+    ///
+    /// Default: `XmlVersion::Xml10`
+    pub xml_version: XmlVersion,
+
+    /// Escape Unicode line breaks as character references.
+    ///
+    /// Affects `U+0085 NEXT LINE`, `U+2028 LINE SEPARATOR` and `U+2029 PARAGRAPH SEPARATOR`
+    /// in text and attribute values, which some consumers treat as line breaks.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>a\u{2028}b</p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>a&#x2028;b</p>
+    /// ```
+    ///
+    /// Default: disabled
+    pub escape_unicode_line_breaks: bool,
+
+    /// Record node positions when `indent` is `None`,
+    /// so the document can be finished via `XmlWriter::end_document_pretty()`.
+    ///
+    /// Requires memory proportional to the number of nodes.
+    ///
+    /// Default: disabled
+    pub record_node_offsets: bool,
+
+    /// Write a space before the slash of self-closing tags, like XHTML.
+    ///
+    /// Has no effect when no tags are self-closed, see `enable_self_closing`
+    /// and `always_self_close`, or when `self_close_marker` already starts with a space.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <br/>
+    /// <img src="a.png"/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <br />
+    /// <img src="a.png" />
+    /// ```
+    ///
+    /// Default: disabled
+    pub space_before_self_close: bool,
+
+    /// Write namespace declarations before other attributes, with both groups sorted by name,
+    /// like Canonical XML does.
+    ///
+    /// Attributes of each element are buffered until the start tag is closed.
+    /// Overrides `XmlWriter::set_attribute_schema_order()`.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <svg id="svg1" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns="http://www.w3.org/2000/svg"/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" id="svg1"/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub namespace_decls_first: bool,
+
+    /// Names of elements that are always self-closed when empty,
+    /// even when `enable_self_closing` is disabled.
+    ///
+    /// # Examples
+    ///
+    /// `&["meta"]` with `enable_self_closing` disabled
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <meta>
+    /// </meta>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <meta/>
+    /// ```
+    ///
+    /// Default: `&[]`
+    pub always_self_close: &'static [&'static str],
+
+    /// Omit the `encoding="UTF-8"` declaration attribute when the whole document is ASCII.
+    ///
+    /// When the UTF-8 declaration is written, the output is held in memory and tracked
+    /// for non-ASCII characters. Once one is written, the encoding is kept and the output
+    /// is written out as usual. Data written via `XmlWriter::write_attribute_raw()`
+    /// cannot be tracked, so it keeps the encoding as well. Otherwise, the encoding
+    /// is removed by `XmlWriter::end_document()`.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <?xml version="1.0" encoding="UTF-8" standalone="no"?>
+    /// <svg/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <?xml version="1.0" standalone="no"?>
+    /// <svg/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub omit_encoding_when_ascii: bool,
+
+    /// Names of elements which text is written as CDATA, like HTML `script` and `style`.
+    ///
+    /// Affects `XmlWriter::write_text()` and similar methods.
+    /// Text containing the literal `]]>` is still escaped.
+    ///
+    /// # Examples
+    ///
+    /// `&["script"]`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <script>
+    ///     if (a &lt; b) {}
+    /// </script>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <script><![CDATA[
+    ///     if (a < b) {}
+    /// ]]></script>
+    /// ```
+    ///
+    /// Default: `&[]`
+    pub cdata_content_elements: &'static [&'static str],
+
+    /// Always write a new line right after the start tag of an element with children,
+    /// whatever the first child is.
+    ///
+    /// Overrides `inline_text` and moves CDATA sections onto their own line.
+    /// Empty elements are still self-closed, since the new line is written
+    /// only once the first child is known.
+    /// Has no effect when `indent` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <script><![CDATA[
+    ///     text
+    /// ]]></script>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <script>
+    ///     <![CDATA[
+    ///     text
+    /// ]]></script>
+    /// ```
+    ///
+    /// Default: disabled
+    pub newline_after_open_tag: bool,
+
+    /// The end of a self-closing tag.
+    ///
+    /// Must be `/` followed by anything and then `>`, optionally preceded by spaces.
+    /// See `Options::validate()`.
+    ///
+    /// # Examples
+    ///
+    /// `" />"`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <br/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <br />
+    /// ```
+    ///
+    /// Default: `/>`
+    pub self_close_marker: &'static str,
+
+    /// Skip empty text nodes.
+    ///
+    /// By default, writing an empty text still opens the element,
+    /// so it's never self-closed, even when whitespaces are preserved.
+    ///
+    /// # Examples
+    ///
+    /// `XmlWriter::write_text("")` with preserved whitespaces
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p></p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub skip_empty_text: bool,
+
+    /// Escape attribute values using HTML rules.
+    ///
+    /// Only `&` and the quote used around the value are escaped in attribute values.
+    /// Text is escaped as usual, see `skip_text_gt_escaping`.
+    ///
+    /// **Warning:** the output is not a valid XML when attribute values contain `<`.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <a title="a &lt; b &amp; c"/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <a title="a < b &amp; c"/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub html_mode: bool,
+
+    /// Do not escape `>` in text nodes, which HTML doesn't require.
+    ///
+    /// Overrides `text_gt_escaping`, which exists for XML, where `]]>` is not allowed in text.
+    ///
+    /// Since this is not a valid XML, works only when `html_mode` is enabled.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>a &gt; b</p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>a > b</p>
+    /// ```
+    ///
+    /// Default: disabled
+    pub skip_text_gt_escaping: bool,
+
+    /// Wrap comments longer than the specified column at word boundaries.
+    ///
+    /// The comment text is moved onto separate lines, indented one level deeper.
+    /// Consecutive whitespaces between words are collapsed into a single space.
+    ///
+    /// Has no effect when indention is disabled or when whitespaces are preserved.
+    ///
+    /// # Examples
+    ///
+    /// `Some(20)`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <p>
+    ///     <!--A very long comment, indeed.-->
+    /// </p>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <p>
+    ///     <!--
+    ///         A very long
+    ///         comment, indeed.
+    ///     -->
+    /// </p>
+    /// ```
+    ///
+    /// Default: `None`
+    pub comment_wrap_width: Option<usize>,
+
+    /// Wrap base64 text written by `XmlWriter::write_base64_element()`
+    /// after the specified number of characters.
+    ///
+    /// Lines are wrapped at 4-character boundaries, so the width is rounded down
+    /// to a multiple of 4, but not below 4. The indention is not counted.
+    /// Continuation lines are indented like the text node itself.
+    ///
+    /// # Examples
+    ///
+    /// `Some(8)`
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <blob>
+    ///     Zm9vYmFyYmF6
+    /// </blob>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <blob>
+    ///     Zm9vYmFy
+    ///     YmF6
+    /// </blob>
+    /// ```
+    ///
+    /// Default: `None`
+    pub base64_wrap_width: Option<usize>,
+
+    /// Write attributes with an empty value as just a name, like HTML boolean attributes.
+    ///
+    /// Since this is not a valid XML, works only when `html_mode` is enabled.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <input checked=""/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <input checked/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub minimize_empty_attributes: bool,
+
+    /// Pad attribute names with spaces, so the `=` signs are aligned in a column.
+    ///
+    /// Works only when `attributes_indent` is set, since each attribute has to be
+    /// on its own line. Attributes are buffered until the start tag is finished
+    /// to find the longest name.
+    ///
+    /// # Examples
+    ///
+    /// Before:
+    ///
+    /// ```text
+    /// <rect
+    ///     id="rect1"
+    ///     stroke-width="2"
+    ///     fill="none"/>
+    /// ```
+    ///
+    /// After:
+    ///
+    /// ```text
+    /// <rect
+    ///     id          ="rect1"
+    ///     stroke-width="2"
+    ///     fill        ="none"/>
+    /// ```
+    ///
+    /// Default: disabled
+    pub align_attribute_names: bool,
+
+    /// A line ending used for new lines between nodes and attributes.
+    ///
+    /// New lines inside text, comments and other content are written as is,
+    /// so content written while whitespaces are preserved keeps its own line endings.
+    ///
+    /// Default: `LineEnding::Lf`
+    pub line_ending: LineEnding,
+}
+
+/// Common typographic characters and their HTML/DocBook entity names.
+///
+/// Used by [`Options::typographic()`].
+///
+/// [`Options::typographic()`]: struct.Options.html#method.typographic
+pub const TYPOGRAPHIC_ENTITIES: &[(char, &str)] = &[
+    ('\u{2018}', "lsquo"),
+    ('\u{2019}', "rsquo"),
+    ('\u{201C}', "ldquo"),
+    ('\u{201D}', "rdquo"),
+    ('\u{2013}', "ndash"),
+    ('\u{2014}', "mdash"),
+    ('\u{2026}', "hellip"),
+    ('\u{00AB}', "laquo"),
+    ('\u{00BB}', "raquo"),
+    ('\u{00A0}', "nbsp"),
+];
+
+impl Options {
+    /// Returns default options with [`TYPOGRAPHIC_ENTITIES`] used as an entity map.
+    ///
+    /// [`TYPOGRAPHIC_ENTITIES`]: constant.TYPOGRAPHIC_ENTITIES.html
+    #[inline]
+    pub fn typographic() -> Self {
+        Options {
+            entity_map: Some(TYPOGRAPHIC_ENTITIES),
+            ..Options::default()
+        }
+    }
+
+    /// Checks that options don't conflict with each other.
+    ///
+    /// Returns the options back, so it can be used right at construction.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    ///
+    /// let opt = Options {
+    ///     indent: Indent::None,
+    ///     attributes_indent: Indent::Spaces(2),
+    ///     ..Options::default()
+    /// };
+    /// assert_eq!(opt.validate().unwrap_err(), OptionsError::AttributesIndentWithoutIndent);
+    /// ```
+    pub fn validate(self) -> core::result::Result<Self, OptionsError> {
+        if self.indent == Indent::None {
+            if self.attributes_indent != Indent::None {
+                return Err(OptionsError::AttributesIndentWithoutIndent);
+            }
+
+            if self.text_wrap_width.is_some() {
+                return Err(OptionsError::TextWrapWithoutIndent);
+            }
+        } else if self.record_node_offsets {
+            return Err(OptionsError::RecordNodeOffsetsWithIndent);
+        }
+
+        if self.space_before_self_close
+            && !self.enable_self_closing
+            && self.always_self_close.is_empty()
+        {
+            return Err(OptionsError::SpaceBeforeSelfCloseWithoutSelfClosing);
+        }
+
+        if self.minimize_empty_attributes && !self.html_mode {
+            return Err(OptionsError::MinimizeEmptyAttributesWithoutHtmlMode);
+        }
+
+        if self.skip_text_gt_escaping && !self.html_mode {
+            return Err(OptionsError::SkipTextGtEscapingWithoutHtmlMode);
+        }
+
+        if self.align_attribute_names && self.attributes_indent == Indent::None {
+            return Err(OptionsError::AlignAttributeNamesWithoutAttributesIndent);
+        }
+
+        let marker = self.self_close_marker.trim_start_matches(' ');
+        if marker.len() < 2 || !marker.starts_with('/') || !marker.ends_with('>') {
+            return Err(OptionsError::InvalidSelfCloseMarker);
+        }
+
+        Ok(self)
+    }
+}
+
+/// A conflict between options, returned by [`Options::validate()`].
+///
+/// [`Options::validate()`]: struct.Options.html#method.validate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OptionsError {
+    /// `attributes_indent` is set, while `indent` is `Indent::None`.
+    AttributesIndentWithoutIndent,
+    /// `text_wrap_width` is set, while `indent` is `Indent::None`.
+    TextWrapWithoutIndent,
+    /// `record_node_offsets` is enabled, while `indent` is not `Indent::None`.
+    RecordNodeOffsetsWithIndent,
+    /// `space_before_self_close` is enabled, while `enable_self_closing` is disabled
+    /// and `always_self_close` is empty.
+    SpaceBeforeSelfCloseWithoutSelfClosing,
+    /// `self_close_marker` doesn't start with `/` and end with `>`.
+    InvalidSelfCloseMarker,
+    /// `minimize_empty_attributes` is enabled, while `html_mode` is disabled.
+    MinimizeEmptyAttributesWithoutHtmlMode,
+    /// `align_attribute_names` is enabled, while `attributes_indent` is `Indent::None`.
+    AlignAttributeNamesWithoutAttributesIndent,
+    /// `skip_text_gt_escaping` is enabled, while `html_mode` is disabled.
+    SkipTextGtEscapingWithoutHtmlMode,
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            OptionsError::AttributesIndentWithoutIndent => {
+                "attributes_indent requires indent to be enabled"
+            }
+            OptionsError::TextWrapWithoutIndent => "text_wrap_width requires indent to be enabled",
+            OptionsError::RecordNodeOffsetsWithIndent => {
+                "record_node_offsets requires indent to be disabled"
+            }
+            OptionsError::SpaceBeforeSelfCloseWithoutSelfClosing => {
+                "space_before_self_close requires enable_self_closing"
+            }
+            OptionsError::InvalidSelfCloseMarker => {
+                "self_close_marker must start with '/' and end with '>'"
+            }
+            OptionsError::MinimizeEmptyAttributesWithoutHtmlMode => {
+                "minimize_empty_attributes requires html_mode"
+            }
+            OptionsError::AlignAttributeNamesWithoutAttributesIndent => {
+                "align_attribute_names requires attributes_indent to be enabled"
+            }
+            OptionsError::SkipTextGtEscapingWithoutHtmlMode => {
+                "skip_text_gt_escaping requires html_mode"
+            }
+        };
+
+        f.write_str(msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OptionsError {}
+
+impl Default for Options {
+    #[inline]
+    fn default() -> Self {
+        Options {
+            use_single_quote: false,
+            indent: Indent::DEFAULT,
+            attributes_indent: Indent::None,
+            enable_self_closing: true,
+            text_wrap_width: None,
+            tab_width: 4,
+            text_gt_escaping: TextGtEscaping::Always,
+            space_between_siblings_when_compact: false,
+            open_element_on_comment: true,
+            entity_map: None,
+            strict: false,
+            trim_trailing_whitespace: false,
+            inline_text: false,
+            indent_root: true,
+            attribute_to_element_if: None,
+            xml_version: XmlVersion::Xml10,
+            escape_unicode_line_breaks: false,
+            record_node_offsets: false,
+            space_before_self_close: false,
+            namespace_decls_first: false,
+            always_self_close: &[],
+            omit_encoding_when_ascii: false,
+            cdata_content_elements: &[],
+            newline_after_open_tag: false,
+            self_close_marker: "/>",
+            skip_empty_text: false,
+            html_mode: false,
+            skip_text_gt_escaping: false,
+            comment_wrap_width: None,
+            base64_wrap_width: None,
+            minimize_empty_attributes: false,
+            align_attribute_names: false,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+// Implemented manually, since older compilers don't implement `Debug`
+// for function pointers with reference arguments.
+impl fmt::Debug for Options {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Options")
+            .field("use_single_quote", &self.use_single_quote)
+            .field("indent", &self.indent)
+            .field("attributes_indent", &self.attributes_indent)
+            .field("enable_self_closing", &self.enable_self_closing)
+            .field("text_wrap_width", &self.text_wrap_width)
+            .field("tab_width", &self.tab_width)
+            .field("text_gt_escaping", &self.text_gt_escaping)
+            .field(
+                "space_between_siblings_when_compact",
+                &self.space_between_siblings_when_compact,
+            )
+            .field("open_element_on_comment", &self.open_element_on_comment)
+            .field("entity_map", &self.entity_map)
+            .field("strict", &self.strict)
+            .field("trim_trailing_whitespace", &self.trim_trailing_whitespace)
+            .field("inline_text", &self.inline_text)
+            .field("indent_root", &self.indent_root)
+            .field(
+                "attribute_to_element_if",
+                &self.attribute_to_element_if.map(|_| ".."),
+            )
+            .field("xml_version", &self.xml_version)
+            .field(
+                "escape_unicode_line_breaks",
+                &self.escape_unicode_line_breaks,
+            )
+            .field("record_node_offsets", &self.record_node_offsets)
+            .field("space_before_self_close", &self.space_before_self_close)
+            .field("namespace_decls_first", &self.namespace_decls_first)
+            .field("always_self_close", &self.always_self_close)
+            .field("omit_encoding_when_ascii", &self.omit_encoding_when_ascii)
+            .field("cdata_content_elements", &self.cdata_content_elements)
+            .field("newline_after_open_tag", &self.newline_after_open_tag)
+            .field("self_close_marker", &self.self_close_marker)
+            .field("skip_empty_text", &self.skip_empty_text)
+            .field("html_mode", &self.html_mode)
+            .field("skip_text_gt_escaping", &self.skip_text_gt_escaping)
+            .field("comment_wrap_width", &self.comment_wrap_width)
+            .field("base64_wrap_width", &self.base64_wrap_width)
+            .field("minimize_empty_attributes", &self.minimize_empty_attributes)
+            .field("align_attribute_names", &self.align_attribute_names)
+            .field("line_ending", &self.line_ending)
+            .finish()
+    }
+}
+
+/// A reusable buffer for the stack of open elements.
+///
+/// Allows reusing an already allocated stack across multiple documents.
+/// See [`XmlWriter::from_parts()`] and [`XmlWriter::into_parts()`].
+///
+/// [`XmlWriter::from_parts()`]: struct.XmlWriter.html#method.from_parts
+/// [`XmlWriter::into_parts()`]: struct.XmlWriter.html#method.into_parts
+#[derive(Clone, Default, Debug)]
+pub struct DepthStack(Vec<DepthData>);
+
+impl DepthStack {
+    /// Creates a new stack with space for at least `capacity` open elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        DepthStack(Vec::with_capacity(capacity))
+    }
+
+    /// Returns the number of open elements the stack can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+/// A token of an open element.
+///
+/// Returned by [`XmlWriter::start_element_token()`] and consumed by
+/// [`XmlWriter::end_element_token()`], which makes sure that elements are closed
+/// in the right order.
+///
+/// [`XmlWriter::start_element_token()`]: struct.XmlWriter.html#method.start_element_token
+/// [`XmlWriter::end_element_token()`]: struct.XmlWriter.html#method.end_element_token
+// Not Copy or Clone on purpose, so a token can be used only once.
+#[allow(missing_copy_implementations)]
+#[must_use = "an element token must be passed to end_element_token()"]
+#[derive(PartialEq, Debug)]
+pub struct ElementToken {
+    depth: usize,
+}
+
+/// A writer passed to the [`XmlWriter::write_attribute_raw_checked()`] closure.
+///
+/// Forwards all data to the underlying writer as is.
+/// With the `std` feature, each write must be valid UTF-8.
+///
+/// When `Options::strict` is enabled, rejects data containing `<`
+/// with an `InvalidData` error.
+///
+/// [`XmlWriter::write_attribute_raw_checked()`]: struct.XmlWriter.html#method.write_attribute_raw_checked
+pub struct RawWriter<'a, W: Write> {
+    fmt_writer: &'a mut FmtWriter<W>,
+    strict: bool,
+}
+
+impl<'a, W: Write> fmt::Debug for RawWriter<'a, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawWriter")
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
+
+const RAW_LT_ERROR: &str = "attribute value must not contain `<`";
+
+#[cfg(feature = "std")]
+impl<'a, W: Write> io::Write for RawWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.strict && buf.contains(&b'<') {
+            return Err(invalid_data_error(RAW_LT_ERROR));
+        }
+
+        let s = std::str::from_utf8(buf)
+            .map_err(|_| invalid_data_error("stream did not contain valid UTF-8"))?;
+        self.fmt_writer.write_raw(s)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.fmt_writer.writer.flush()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'a, W: Write> fmt::Write for RawWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.strict && s.contains('<') {
+            return Err(invalid_data_error(RAW_LT_ERROR));
+        }
+
+        self.fmt_writer.write_raw(s)
+    }
+}
+
+/// A writer passed to the attributes closure of [`XmlWriter::element_scope()`].
+///
+/// Allows writing only attributes of the current element.
+///
+/// ```compile_fail
+/// use xmlwriter::*;
+/// use std::io;
+///
+/// fn main() -> io::Result<()> {
+///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+///     w.element_scope("p", |a| a.write_text("text"), |_| Ok(()))
+/// }
+/// ```
+///
+/// [`XmlWriter::element_scope()`]: struct.XmlWriter.html#method.element_scope
+pub struct AttrWriter<'a, W: Write> {
+    w: &'a mut XmlWriter<W>,
+}
+
+impl<'a, W: Write> fmt::Debug for AttrWriter<'a, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AttrWriter").finish()
+    }
+}
+
+impl<'a, W: Write> AttrWriter<'a, W> {
+    /// Writes an attribute.
+    ///
+    /// See [`XmlWriter::write_attribute()`] for details.
+    ///
+    /// [`XmlWriter::write_attribute()`]: struct.XmlWriter.html#method.write_attribute
+    pub fn write_attribute<V: IntoAttrValue>(&mut self, name: &str, value: V) -> Result<()> {
+        self.w.write_attribute(name, value)
+    }
+
+    /// Writes a formatted attribute value.
+    ///
+    /// See [`XmlWriter::write_attribute_fmt()`] for details.
+    ///
+    /// [`XmlWriter::write_attribute_fmt()`]: struct.XmlWriter.html#method.write_attribute_fmt
+    pub fn write_attribute_fmt(&mut self, name: &str, fmt: fmt::Arguments) -> Result<()> {
+        self.w.write_attribute_fmt(name, fmt)
+    }
+
+    /// Writes an attribute from a `Cow` value.
+    ///
+    /// See [`XmlWriter::write_attribute_cow()`] for details.
+    ///
+    /// [`XmlWriter::write_attribute_cow()`]: struct.XmlWriter.html#method.write_attribute_cow
+    pub fn write_attribute_cow(&mut self, name: &str, value: Cow<str>) -> Result<()> {
+        self.w.write_attribute_cow(name, value)
+    }
+}
+
+/// An `io::Write` implementation that writes into a `String`.
+///
+/// Used by [`StringXmlWriter`]. Data that is not valid UTF-8 results in an `InvalidData` error,
+/// which can happen only with `XmlWriter::write_attribute_raw()`.
+///
+/// [`StringXmlWriter`]: struct.StringXmlWriter.html
+#[cfg(feature = "std")]
+#[derive(Clone, Default, Debug)]
+pub struct StringWriter {
+    buf: String,
+}
+
+#[cfg(feature = "std")]
+impl StringWriter {
+    /// Returns the written string.
+    pub fn into_string(self) -> String {
+        self.buf
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for StringWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = std::str::from_utf8(buf)
+            .map_err(|_| invalid_data_error("stream did not contain valid UTF-8"))?;
+        self.buf.push_str(s);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An XML writer that writes directly into a `String`.
+///
+/// Dereferences to [`XmlWriter`], so all its methods are available.
+/// Since writing into a `String` cannot fail, errors are possible only
+/// when `XmlWriter::write_attribute_raw()` produces invalid UTF-8.
+///
+/// Without the `std` feature, `XmlWriter<String>` can be used directly instead.
+///
+/// [`XmlWriter`]: struct.XmlWriter.html
+///
+/// # Example
+///
+/// ```
+/// use xmlwriter::*;
+/// use std::io;
+///
+/// fn main() -> io::Result<()> {
+///     let mut w = StringXmlWriter::new(Options::default());
+///     w.start_element("svg")?;
+///     w.write_attribute("id", "svg1")?;
+///     assert_eq!(w.end_document(), "<svg id=\"svg1\"/>\n");
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct StringXmlWriter(XmlWriter<StringWriter>);
+
+#[cfg(feature = "std")]
+impl StringXmlWriter {
+    /// Creates a new `StringXmlWriter`.
+    pub fn new(opt: Options) -> Self {
+        StringXmlWriter(XmlWriter::new(StringWriter::default(), opt))
+    }
+
+    /// Closes all open elements and returns the written string.
+    pub fn end_document(self) -> String {
+        self.0
+            .end_document()
+            .expect("closing elements always produces valid UTF-8")
+            .into_string()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Deref for StringXmlWriter {
+    type Target = XmlWriter<StringWriter>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl DerefMut for StringXmlWriter {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// A writer that writes into a `fmt::Formatter`.
+///
+/// Used by [`XmlWriter::from_formatter()`] to implement `Display` via `XmlWriter`.
+/// Formatter errors are returned as `io::ErrorKind::Other`.
+///
+/// [`XmlWriter::from_formatter()`]: struct.XmlWriter.html#method.from_formatter
+pub struct FmtSink<'a, 'b: 'a> {
+    f: &'a mut fmt::Formatter<'b>,
+}
+
+impl<'a, 'b: 'a> fmt::Debug for FmtSink<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FmtSink").finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, 'b: 'a> io::Write for FmtSink<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = std::str::from_utf8(buf)
+            .map_err(|_| invalid_data_error("stream did not contain valid UTF-8"))?;
+        self.f.write_str(s).map_err(|_| formatter_error())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'a, 'b: 'a> fmt::Write for FmtSink<'a, 'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.f.write_str(s)
+    }
+}
+
+impl<'a, 'b: 'a> XmlWriter<FmtSink<'a, 'b>> {
+    /// Creates a new `XmlWriter`, writing data in the formatter.
+    ///
+    /// Allows implementing `Display` via `XmlWriter`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::fmt;
+    ///
+    /// struct Point(i32, i32);
+    ///
+    /// impl fmt::Display for Point {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         let opt = Options { indent: Indent::None, ..Options::default() };
+    ///         let mut w = XmlWriter::from_formatter(f, opt);
+    ///         w.start_element("point").map_err(|_| fmt::Error)?;
+    ///         w.write_attribute("x", &self.0).map_err(|_| fmt::Error)?;
+    ///         w.write_attribute("y", &self.1).map_err(|_| fmt::Error)?;
+    ///         w.end_document().map_err(|_| fmt::Error)?;
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(Point(1, 2).to_string(), "<point x=\"1\" y=\"2\"/>");
+    /// ```
+    #[inline]
+    pub fn from_formatter(f: &'a mut fmt::Formatter<'b>, opt: Options) -> Self {
+        Self::new(FmtSink { f }, opt)
+    }
+}
+
+/// An observer of the nodes written by [`XmlWriter`].
+///
+/// Each method is called right after the corresponding markup was written,
+/// with the position where the node starts.
+/// Lines and columns are 1-based and columns are counted in characters.
+///
+/// Data written via [`XmlWriter::write_attribute_raw()`] is not accounted for.
+///
+/// All methods do nothing by default.
+///
+/// [`XmlWriter`]: struct.XmlWriter.html
+/// [`XmlWriter::write_attribute_raw()`]: struct.XmlWriter.html#method.write_attribute_raw
+pub trait XmlEvents {
+    /// Called after an element start tag, like `<tag`, was written.
+    fn on_start_element(&mut self, name: &str, line: usize, column: usize) {
+        let _ = (name, line, column);
+    }
+
+    /// Called after an element end tag, like `</tag>` or `/>`, was written.
+    fn on_end_element(&mut self, name: &str, line: usize, column: usize) {
+        let _ = (name, line, column);
+    }
+
+    /// Called after a text node, including CDATA, was written.
+    fn on_text(&mut self, line: usize, column: usize) {
+        let _ = (line, column);
+    }
+}
+
+/// A value accepted by [`XmlWriter::write_attribute()`].
+///
+/// Implemented for references to `fmt::Display` objects
+/// and for `Option`s of `fmt::Display` objects, where `None` means no attribute.
+///
+/// [`XmlWriter::write_attribute()`]: struct.XmlWriter.html#method.write_attribute
+pub trait IntoAttrValue {
+    /// The value to write.
+    type Value: Display;
+
+    /// Returns the value to write or `None` to skip the attribute.
+    fn into_attr_value(self) -> Option<Self::Value>;
+}
+
+impl<'a, T: Display + ?Sized> IntoAttrValue for &'a T {
+    type Value = &'a T;
+
+    #[inline]
+    fn into_attr_value(self) -> Option<Self::Value> {
+        Some(self)
+    }
+}
+
+impl<T: Display> IntoAttrValue for Option<T> {
+    type Value = T;
+
+    #[inline]
+    fn into_attr_value(self) -> Option<Self::Value> {
+        self
+    }
+}
+
+/// A value that can be written as an attribute via [`XmlWriter::write_attribute_as()`].
+///
+/// Unlike `fmt::Display`, intended solely for XML serialization,
+/// which is handy for enums written as attribute tokens.
+///
+/// [`XmlWriter::write_attribute_as()`]: struct.XmlWriter.html#method.write_attribute_as
+pub trait AsXmlAttr {
+    /// Returns the attribute value. It will be escaped.
+    fn as_xml_attr(&self) -> Cow<'_, str>;
+}
+
+/// A type that writes its fields as attributes via [`XmlWriter::start_element_with()`].
+///
+/// [`XmlWriter::start_element_with()`]: struct.XmlWriter.html#method.start_element_with
+pub trait WriteAttributes {
+    /// Writes attributes of the current element.
+    fn write_attributes<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()>;
+}
+
+// Cached indention strings, which are grown on demand.
+#[derive(Clone, Default, Debug)]
+struct Padding {
+    spaces: String,
+    tabs: String,
+}
+
+// A wrapper to keep `XmlWriter` cloneable and debuggable.
+// Observers cannot be cloned, so a cloned writer has none.
+#[derive(Default)]
+struct EventObserver(Option<Box<dyn XmlEvents>>);
+
+impl Clone for EventObserver {
+    fn clone(&self) -> Self {
+        EventObserver(None)
+    }
+}
+
+impl fmt::Debug for EventObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "EventObserver(Some(..))"
+        } else {
+            "EventObserver(None)"
+        })
+    }
+}
+
+// An error returned by the underlying writer during formatting,
+// since fmt::Write can only return an empty fmt::Error.
+// io::Error cannot be cloned, so a cloned writer has none.
+#[cfg(feature = "std")]
+#[derive(Default, Debug)]
+struct PendingError(Option<io::Error>);
+
+#[cfg(feature = "std")]
+impl Clone for PendingError {
+    fn clone(&self) -> Self {
+        PendingError(None)
+    }
+}
+
+// The writer state right before the current element start tag.
+// See XmlWriter::cancel_element(), which requires `std`.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+#[derive(Clone, Copy, Debug)]
+struct ElementStart {
+    state: State,
+    root_written: bool,
+    text_end: Option<usize>,
+    new_line_end: usize,
+    node_offsets_len: usize,
+    line: usize,
+    column: usize,
+    offset: usize,
+    text_brackets: usize,
+    // The output offset right after the element name.
+    name_end: usize,
+    // Set when the start tag of the parent element was closed by this element.
+    parent_opened: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Empty,
+    Document,
+    Attributes,
+    CData,
+}
+
+#[derive(Clone, Debug)]
+struct DepthData {
+    element_name: Option<String>,
+    has_children: bool,
+    // The output offset of the text written right after the start tag,
+    // when `Options::inline_text` is enabled.
+    inline_start: Option<usize>,
+    // Keep attributes on the same line as the element name.
+    // See XmlWriter::start_element_nowrap().
+    nowrap: bool,
+}
+
+// This wrapper writer is so that we can make sure formatted strings are properly escaped too,
+// as we don't have access to the formatting stuff without a fmt::Write implementation, so
+// we provide it by wrapping the writer given to us while escaping appropriately any string to
+// be written, depending on the type of node we're writing.
+#[derive(Clone, Debug)]
+struct FmtWriter<W: Write> {
+    writer: W,
+    #[cfg(feature = "std")]
+    error: PendingError,
+    // Set to None once the text is written, as a way to make sure the code
+    // sets the proper escaping type before using the fmt_writer.write_str().
+    escape: Option<Escape>,
+    // Same as for Options, but kept available for write_escaped()
+    use_single_quote: bool,
+    text_gt_escaping: TextGtEscaping,
+    entity_map: Option<&'static [(char, &'static str)]>,
+    xml_version: XmlVersion,
+    escape_unicode_line_breaks: bool,
+    html_mode: bool,
+    // The number of consecutive `]` written at the end of the current text node,
+    // so we can detect `]]>` even when it's split between chunks.
+    text_brackets: usize,
+    // The last character of the current comment, so `--` can be detected
+    // even when it's split between chunks. None at the comment start.
+    comment_last: Option<char>,
+    // The current position in the output, 1-based. Used by XmlEvents.
+    // Counted only when track_position is set, since it requires checking each byte.
+    line: usize,
+    column: usize,
+    track_position: bool,
+    // The number of bytes written so far.
+    offset: usize,
+    // The number of bytes passed on to the writer or the buffer so far.
+    // Unlike offset, doesn't include held back whitespaces and captured attributes.
+    written: usize,
+    trim_trailing_whitespace: bool,
+    // Spaces and tabs at the end of the current line, not yet written.
+    // Dropped on a new line and written out before anything else.
+    pending_whitespace: String,
+    // When set, the output is collected into `captured` instead.
+    // Used to reorder attributes.
+    capturing: bool,
+    captured: String,
+    // When non-zero, the output is held in `buffer` instead of being written.
+    // Used by XmlWriter::write_element_if_nonempty(), which can be nested.
+    buffering: usize,
+    buffer: String,
+    // The range of the declaration encoding attribute in the buffered output.
+    // Set while the output is held and ASCII-only. See Options::omit_encoding_when_ascii.
+    encoding_range: Option<(usize, usize)>,
+}
+
+impl<W: Write> FmtWriter<W> {
+    #[cfg(feature = "std")]
+    fn take_err(&mut self) -> io::Error {
+        // This avoids forgetting to set it to the appropriate value when calling write_fmt().
+        // We can't do it in FmtWriter's write_str(), since with a real format string the method
+        // will be called several times so it'll fail in the expect() below as we'll have set
+        // self.escape back to None.
+        self.escape = None;
+
+        // Moving the error out also makes sure we can detect if take_err() is called
+        // without having an error that happened beforehand.
+        self.error
+            .0
+            .take()
+            .expect("there must have been an error before calling take_err()!")
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn take_err(&mut self) -> fmt::Error {
+        // fmt::Error carries no information, so there is nothing to preserve.
+        self.escape = None;
+        fmt::Error
+    }
+
+    // Writes a string as is, without any escaping.
+    #[inline]
+    fn write_raw(&mut self, s: &str) -> Result<()> {
+        self.advance(s.as_bytes());
+        if self.trim_trailing_whitespace {
+            self.write_trimmed(s)
+        } else {
+            self.write_out(s)
+        }
+    }
+
+    #[inline]
+    fn write_out(&mut self, s: &str) -> Result<()> {
+        if self.capturing {
+            self.captured.push_str(s);
+            Ok(())
+        } else {
+            self.write_direct(s)
+        }
+    }
+
+    #[inline]
+    fn write_direct(&mut self, s: &str) -> Result<()> {
+        self.written += s.len();
+        if self.buffering != 0 {
+            self.buffer.push_str(s);
+            // The encoding is kept anyway, so there is no need to hold the output.
+            // Nested buffers can still be dropped, so they are checked only at the end.
+            if self.buffering == 1 && self.encoding_range.is_some() && !s.is_ascii() {
+                return self.release_encoding();
+            }
+            Ok(())
+        } else {
+            self.write_to_writer(s)
+        }
+    }
+
+    // Stops holding the output for Options::omit_encoding_when_ascii,
+    // keeping the encoding attribute.
+    fn release_encoding(&mut self) -> Result<()> {
+        if self.encoding_range.take().is_none() {
+            return Ok(());
+        }
+
+        self.buffering -= 1;
+        let buffer = core::mem::replace(&mut self.buffer, String::new());
+        let result = self.write_to_writer(&buffer);
+        // Keep the buffer capacity.
+        self.buffer = buffer;
+        self.buffer.clear();
+        result
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn write_to_writer(&mut self, s: &str) -> Result<()> {
+        self.writer.write_all(s.as_bytes())
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn write_to_writer(&mut self, s: &str) -> Result<()> {
+        self.writer.write_str(s)
+    }
+
+    // Writes a string, holding back spaces and tabs at the end of a line.
+    fn write_trimmed(&mut self, s: &str) -> Result<()> {
+        let mut lines = s.split('\n').peekable();
+        while let Some(mut line) = lines.next() {
+            let is_last = lines.peek().is_none();
+            // Whitespaces before a CRLF line ending are trimmed as well.
+            let is_crlf = !is_last && line.ends_with('\r');
+            if is_crlf {
+                line = &line[..line.len() - 1];
+            }
+
+            self.write_line_part(line)?;
+
+            if !is_last {
+                self.pending_whitespace.clear();
+                self.write_out(if is_crlf { "\r\n" } else { "\n" })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_line_part(&mut self, line: &str) -> Result<()> {
+        let len = line.trim_end_matches(|c| c == ' ' || c == '\t').len();
+        if len != 0 {
+            self.flush_pending_whitespace()?;
+            self.write_out(&line[..len])?;
+        }
+
+        self.pending_whitespace.push_str(&line[len..]);
+        Ok(())
+    }
+
+    fn flush_pending_whitespace(&mut self) -> Result<()> {
+        if !self.pending_whitespace.is_empty() {
+            let pending = core::mem::replace(&mut self.pending_whitespace, String::new());
+            self.write_out(&pending)?;
+            // Keep the buffer capacity.
+            self.pending_whitespace = pending;
+            self.pending_whitespace.clear();
+        }
+
+        Ok(())
+    }
+
+    fn advance(&mut self, bytes: &[u8]) {
+        self.offset += bytes.len();
+        if self.track_position {
+            count_position(bytes, &mut self.line, &mut self.column);
+        }
+    }
+
+    // Resets the position to the start of a new writer,
+    // which will receive everything that is still held back.
+    // Returns the number of held back bytes.
+    fn rebase_position(&mut self) -> usize {
+        self.line = 1;
+        self.column = 1;
+
+        let held_back = [&self.buffer, &self.captured, &self.pending_whitespace];
+        let mut len = 0;
+        for s in held_back.iter() {
+            len += s.len();
+            if self.track_position {
+                count_position(s.as_bytes(), &mut self.line, &mut self.column);
+            }
+        }
+
+        len
+    }
+
+    // Separates hyphens with a space, since `--` is not allowed in comments.
+    // A leading `>` is separated as well, since `<!-->` is not a valid comment start in HTML.
+    fn write_comment_escaped(&mut self, s: &str) -> Result<()> {
+        let mut part_start_pos = 0;
+        for (pos, c) in s.char_indices() {
+            let needs_space = match (self.comment_last, c) {
+                (None, '>') | (Some('-'), '-') => true,
+                _ => false,
+            };
+
+            if needs_space {
+                self.write_raw(&s[part_start_pos..pos])?;
+                self.write_raw(" ")?;
+                part_start_pos = pos;
+            }
+
+            self.comment_last = Some(c);
+        }
+
+        self.write_raw(&s[part_start_pos..])
+    }
+
+    // Writes a character reference, like `&#x1B;`, with at least two digits.
+    fn write_char_ref(&mut self, c: char) -> Result<()> {
+        let c = c as usize;
+        // `&#x` + up to 6 digits + `;`
+        let mut buf = [0; 10];
+        buf[..3].copy_from_slice(b"&#x");
+        let mut len = 3;
+        let mut shift = 20;
+        while shift > 4 && (c >> shift) == 0 {
+            shift -= 4;
+        }
+        loop {
+            buf[len] = HEX_DIGITS[(c >> shift) & 0xF];
+            len += 1;
+            if shift == 0 {
+                break;
+            }
+            shift -= 4;
+        }
+        buf[len] = b';';
+        len += 1;
+        self.write_raw(core::str::from_utf8(&buf[..len]).expect("character reference is ASCII"))
+    }
+
+    // Escaping is performed in place, without any allocations. See tests/alloc.rs.
+    fn write_escaped(&mut self, s: &str, escape_quotes: bool) -> Result<()> {
+        let mut part_start_pos = 0;
+        for (pos, c) in s.char_indices() {
+            // Quotes are escaped only in attribute values.
+            let is_text = !escape_quotes;
+            let brackets = self.text_brackets;
+            if is_text {
+                self.text_brackets = if c == ']' { brackets + 1 } else { 0 };
+            }
+
+            let is_control = match c {
+                '\t' | '\n' | '\r' => false,
+                '\u{0}'..='\u{1F}' => true,
+                // Restricted characters that must be escaped in XML 1.1.
+                '\u{7F}'..='\u{84}' | '\u{86}'..='\u{9F}' => self.xml_version == XmlVersion::Xml11,
+                _ => false,
+            };
+
+            if is_control && (c == '\0' || self.xml_version == XmlVersion::Xml10) {
+                return Err(invalid_data_error("invalid XML character"));
+            }
+
+            let is_line_break = match c {
+                '\u{85}' | '\u{2028}' | '\u{2029}' => self.escape_unicode_line_breaks,
+                _ => false,
+            };
+
+            if is_control || is_line_break {
+                self.write_raw(&s[part_start_pos..pos])?;
+                self.write_char_ref(c)?;
+                part_start_pos = pos + c.len_utf8();
+                continue;
+            }
+
+            // HTML attribute values require escaping only `&` and quotes.
+            let escape_lt = is_text || !self.html_mode;
+            let escape_gt = if is_text {
+                match self.text_gt_escaping {
+                    TextGtEscaping::Always => true,
+                    TextGtEscaping::OnlyAfterBrackets => brackets >= 2,
+                    TextGtEscaping::Never => false,
+                }
+            } else {
+                !self.html_mode
+            };
+
+            let escaped_char = escape_char(
+                c,
+                escape_lt,
+                escape_gt,
+                escape_quotes,
+                self.use_single_quote,
+            );
+
+            // Mapped entities override the built-in escapes everywhere,
+            // while other characters are mapped only in text.
+            let entity = match self.entity_map {
+                Some(map) if is_text || escaped_char.is_some() => map
+                    .iter()
+                    .find(|&&(mapped, _)| mapped == c)
+                    .map(|&(_, name)| name),
+                _ => None,
+            };
+
+            if escaped_char.is_some() || entity.is_some() {
+                // We have a character to escape, so write the previous part and the escaped character.
+                self.write_raw(&s[part_start_pos..pos])?;
+                if let Some(entity) = entity {
+                    self.write_raw("&")?;
+                    self.write_raw(entity)?;
+                    self.write_raw(";")?;
+                } else if let Some(escaped_char) = escaped_char {
+                    self.write_raw(escaped_char)?;
+                }
+                // Skip the escaped character from part, for afterwards
+                part_start_pos = pos + c.len_utf8();
+            }
+            // There's nothing to be done if the character doesn't need to be escaped, as we'll either
+            // wait until we get an escapable character, or wait until the end of the string where we'll
+            // just write out the rest of the string.
+        }
+        // Write the rest of the string which needs no escaping
+        self.write_raw(&s[part_start_pos..])
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Escape {
+    Comment,
+    AttributeValue,
+    Text,
+    CData,
+    Raw,
+}
+
+impl<W: Write> fmt::Write for FmtWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let result = match self
+            .escape
+            .expect("You must have set self.escape to Some(…) before using the formatter!")
+        {
+            Escape::AttributeValue => self.write_escaped(s, true),
+            Escape::Text => self.write_escaped(s, false),
+            Escape::Comment => self.write_comment_escaped(s),
+            Escape::CData => self.write_raw(s),
+            Escape::Raw => self.write_raw(s),
+        };
+
+        // Keep the original error, so it can be returned by take_err().
+        #[cfg(feature = "std")]
+        let result = result.map_err(|e| self.error.0 = Some(e));
+
+        result.map_err(|_| fmt::Error)
+    }
+}
+
+/// An XML writer.
+#[derive(Clone, Debug)]
+pub struct XmlWriter<W: Write> {
+    // When you control what you're writing enough that you know the bytes are already escaped or
+    // don't need escaping at all, then use fmt_writer.write_raw()?; directly. Otherwise,
+    // set fmt_writer.escape to the appropriate escaping type and use fmt_writer.write_fmt()?; or
+    // fmt_writer.write_str()?; if you are only printing a string directly without formatting, but
+    // still want escaping to be done.
+    fmt_writer: FmtWriter<W>,
+    state: State,
+    preserve_whitespaces: bool,
+    bom_written: bool,
+    doctype_written: bool,
+    root_written: bool,
+    observer: EventObserver,
+    depth_stack: Vec<DepthData>,
+    // A reusable buffer for values built by user closures.
+    scratch: String,
+    // Namespace prefixes declared via with_namespace().
+    namespaces: Vec<String>,
+    padding: Padding,
+    // The depth added to all nodes.
+    base_depth: usize,
+    // See set_attribute_schema_order().
+    attribute_order: Option<&'static [&'static str]>,
+    // Captured attributes of the current element, as (rank, start, end) ranges
+    // of the captured output, when attribute_order is set.
+    attribute_ranges: Vec<(usize, usize, usize)>,
+    // Positions where new lines would be written in the indented mode,
+    // as (byte offset, depth) pairs. See Options::record_node_offsets.
+    node_offsets: Vec<(usize, usize)>,
+    // Attributes of the current element to be written as child elements.
+    // See Options::attribute_to_element_if.
+    attribute_children: Vec<(String, String)>,
+    // Set between begin_attribute() and end_attribute().
+    attribute_open: bool,
+    // The output offset right after the last text node.
+    // When it matches the current offset, nothing was written after that text.
+    text_end: Option<usize>,
+    // The output offset right after the last new line written between nodes.
+    new_line_end: usize,
+    // See cancel_element().
+    element_start: Option<ElementStart>,
+    // The output offset at which the current writer starts. See swap_writer().
+    writer_start: usize,
+    opt: Options,
+}
+
+impl<W: Write> XmlWriter<W> {
+    /// Creates a new `XmlWriter`, writing data in the writer.
+    #[inline]
+    pub fn new(writer: W, opt: Options) -> Self {
+        Self::from_parts(writer, opt, DepthStack::default())
+    }
+
+    /// Creates a new `XmlWriter` reusing a stack returned by [`into_parts()`].
+    ///
+    /// The stack is cleared, but its capacity is kept.
+    ///
+    /// [`into_parts()`]: struct.XmlWriter.html#method.into_parts
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut stack = DepthStack::with_capacity(16);
+    ///     for _ in 0..2 {
+    ///         let mut w = XmlWriter::from_parts(Vec::<u8>::new(), Options::default(), stack);
+    ///         w.start_element("svg")?;
+    ///         let (data, s) = w.into_parts()?;
+    ///         assert_eq!(data, b"<svg/>\n");
+    ///         stack = s;
+    ///     }
+    ///     assert!(stack.capacity() >= 16);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub fn from_parts(writer: W, opt: Options, stack: DepthStack) -> Self {
+        let mut depth_stack = stack.0;
+        depth_stack.clear();
+
+        XmlWriter {
+            fmt_writer: FmtWriter {
+                writer,
+                #[cfg(feature = "std")]
+                error: PendingError::default(),
+                escape: None,
+                use_single_quote: opt.use_single_quote,
+                text_gt_escaping: if opt.html_mode && opt.skip_text_gt_escaping {
+                    TextGtEscaping::Never
+                } else {
+                    opt.text_gt_escaping
+                },
+                entity_map: opt.entity_map,
+                xml_version: opt.xml_version,
+                escape_unicode_line_breaks: opt.escape_unicode_line_breaks,
+                html_mode: opt.html_mode,
+                text_brackets: 0,
+                comment_last: None,
+                line: 1,
+                column: 1,
+                track_position: false,
+                offset: 0,
+                written: 0,
+                trim_trailing_whitespace: opt.trim_trailing_whitespace,
+                pending_whitespace: String::new(),
+                capturing: false,
+                captured: String::new(),
+                buffering: 0,
+                encoding_range: None,
+                buffer: String::new(),
+            },
+            state: State::Empty,
+            preserve_whitespaces: false,
+            bom_written: false,
+            doctype_written: false,
+            root_written: false,
+            observer: EventObserver::default(),
+            depth_stack,
+            scratch: String::new(),
+            namespaces: Vec::new(),
+            padding: Padding::default(),
+            base_depth: 0,
+            attribute_order: None,
+            attribute_ranges: Vec::new(),
+            node_offsets: Vec::new(),
+            attribute_children: Vec::new(),
+            attribute_open: false,
+            text_end: None,
+            new_line_end: 0,
+            element_start: None,
+            writer_start: 0,
+            opt,
+        }
+    }
+
+    /// Replaces the underlying writer, returning the previous one.
+    ///
+    /// The writer state, open elements and options are kept intact,
+    /// so writing continues exactly where it stopped.
+    ///
+    /// The previous writer is not flushed. Data that was not yet written to it,
+    /// like reordered attributes or trailing whitespaces, goes to the new writer.
+    ///
+    /// Positions reported to [`XmlEvents`] and node offsets recorded
+    /// via `Options::record_node_offsets` are relative to the new writer.
+    ///
+    /// [`XmlEvents`]: trait.XmlEvents.html
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     let head = w.swap_writer(Vec::new());
+    ///     w.start_element("rect")?;
+    ///     assert_eq!(head.as_slice(), b"<svg");
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// ">
+    ///     <rect/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn swap_writer(&mut self, writer: W) -> W {
+        // The current element is no longer in this writer.
+        self.element_start = None;
+
+        let held_back_len = self.fmt_writer.rebase_position();
+        self.writer_start = self.fmt_writer.offset - held_back_len;
+        let writer_start = self.writer_start;
+        self.node_offsets.retain(|node| node.0 >= writer_start);
+
+        core::mem::replace(&mut self.fmt_writer.writer, writer)
+    }
+
+    /// Sets an observer that will be notified about each written node.
+    ///
+    /// Lines and columns are counted only after an observer was set,
+    /// so it should be set before writing anything.
+    ///
+    /// A cloned `XmlWriter` has no observer.
+    pub fn set_event_observer(&mut self, observer: Box<dyn XmlEvents>) {
+        self.fmt_writer.track_position = true;
+        self.observer.0 = Some(observer);
+    }
+
+    /// Removes and returns the current observer.
+    pub fn take_event_observer(&mut self) -> Option<Box<dyn XmlEvents>> {
+        self.observer.0.take()
+    }
+
+    /// Writes a byte order mark, i.e. `U+FEFF`.
+    ///
+    /// Since `XmlWriter` always produces UTF-8, the mark is written as UTF-8 as well.
+    ///
+    /// # Panics
+    ///
+    /// - When called not at the very start of the document.
+    #[inline(never)]
+    pub fn write_bom(&mut self) -> Result<()> {
+        if self.state != State::Empty || self.bom_written {
+            panic!("BOM must be written at the start of the document");
+        }
+
+        self.fmt_writer.write_raw("\u{FEFF}")?;
+        // BOM is not a visible character.
+        self.fmt_writer.column = 1;
+        self.bom_written = true;
+
+        Ok(())
+    }
+
+    /// Writes an XML declaration.
+    ///
+    /// `<?xml version="1.0" encoding="UTF-8" standalone="no"?>`
+    ///
+    /// # Panics
+    ///
+    /// - When called twice.
+    #[inline(never)]
+    pub fn write_declaration(&mut self) -> Result<()> {
+        self.write_declaration_with_encoding("UTF-8")
+    }
+
+    /// Writes an XML declaration with a custom encoding.
+    ///
+    /// `<?xml version="1.0" encoding="..." standalone="no"?>`
+    ///
+    /// **Warning:** `XmlWriter` always produces UTF-8, so the output must be transcoded
+    /// into the declared encoding afterwards. Otherwise, the document would be invalid.
+    ///
+    /// # Panics
+    ///
+    /// - When called twice.
+    /// - When the encoding name is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_bom()?;
+    ///     w.write_declaration_with_encoding("UTF-16")?;
+    ///     // Transcode into UTF-16 here.
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "\u{FEFF}<?xml version=\"1.0\" encoding=\"UTF-16\" standalone=\"no\"?>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_declaration_with_encoding(&mut self, encoding: &str) -> Result<()> {
+        if self.state != State::Empty {
+            panic!("declaration was already written");
+        }
+
+        if !is_valid_encoding_name(encoding) {
+            panic!("invalid encoding name");
+        }
+
+        // Pretend that we are writing an element.
+        self.state = State::Attributes;
+
+        // <?xml version='1.0' encoding='UTF-8' standalone='yes'?>
+        self.fmt_writer.write_raw("<?xml")?;
+        // We don't use write_raw() directly so that we get quoting handling for free.
+        // However we can skip escaping here as we perfectly know there's no
+        // escaping needed, albeit the performance impact would be almost inexistent if
+        // we did use the regular method.
+        let version = match self.opt.xml_version {
+            XmlVersion::Xml10 => "1.0",
+            XmlVersion::Xml11 => "1.1",
+        };
+        self.write_attribute_unescaped("version", version)?;
+
+        // Hold the document until the end to check whether it's ASCII.
+        let omit_encoding = self.opt.omit_encoding_when_ascii
+            && encoding.eq_ignore_ascii_case("UTF-8")
+            && self.fmt_writer.buffering == 0;
+        if omit_encoding {
+            self.fmt_writer.buffering += 1;
+        }
+
+        let start = self.fmt_writer.buffer.len();
+        self.write_attribute_unescaped("encoding", encoding)?;
+        if omit_encoding {
+            self.fmt_writer.encoding_range = Some((start, self.fmt_writer.buffer.len()));
+        }
+
+        self.write_attribute_unescaped("standalone", "no")?;
+        self.fmt_writer.write_raw("?>")?;
+
+        self.state = State::Document;
+
+        Ok(())
+    }
+
+    /// Writes an HTML5 doctype.
+    ///
+    /// `<!DOCTYPE html>`
+    ///
+    /// # Panics
+    ///
+    /// - When called after the root element.
+    /// - When a doctype was already written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_html5_doctype()?;
+    ///     w.start_element("html")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<!DOCTYPE html>\n<html/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_html5_doctype(&mut self) -> Result<()> {
+        if self.root_written {
+            panic!("doctype must be written before the root element");
+        }
+
+        if self.doctype_written {
+            panic!("doctype was already written");
+        }
+
+        if self.state != State::Empty {
+            self.write_new_line()?;
+        }
+
+        self.fmt_writer.write_raw("<!DOCTYPE html>")?;
+
+        self.state = State::Document;
+        self.doctype_written = true;
+
+        Ok(())
+    }
+
+    /// Writes a doctype with optional external identifiers and an internal subset.
+    ///
+    /// `<!DOCTYPE name PUBLIC "public_id" "system_id" [subset]>`
+    ///
+    /// Without a public id, the `SYSTEM "system_id"` form is used.
+    /// The internal subset is written by a closure as is, usually via `write_raw_fmt()`.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the root name is not a valid XML name.
+    /// - `InvalidData` when a public id is set without a system id.
+    /// - `InvalidData` when the public id contains characters not allowed by the XML spec.
+    /// - `InvalidData` when the system id contains both `"` and `'`.
+    ///
+    /// Nothing is written in these cases.
+    ///
+    /// # Panics
+    ///
+    /// - When called after the root element.
+    /// - When a doctype was already written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_doctype_full(
+    ///         "note",
+    ///         None,
+    ///         Some("note.dtd"),
+    ///         Some(|w: &mut XmlWriter<_>| w.write_raw_fmt(format_args!("<!ENTITY me \"Me\">"))),
+    ///     )?;
+    ///     w.start_element("note")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<!DOCTYPE note SYSTEM \"note.dtd\" [<!ENTITY me \"Me\">]>\n<note/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_doctype_full<F>(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+        subset: Option<F>,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        if self.root_written {
+            panic!("doctype must be written before the root element");
+        }
+
+        if self.doctype_written {
+            panic!("doctype was already written");
+        }
+
+        if !is_xml_name(name) {
+            return Err(invalid_data_error("invalid doctype name"));
+        }
+
+        if let Some(public_id) = public_id {
+            if system_id.is_none() {
+                return Err(invalid_data_error("doctype public id requires a system id"));
+            }
+
+            if !public_id.chars().all(is_pubid_char) {
+                return Err(invalid_data_error("invalid doctype public id"));
+            }
+        }
+
+        // The system id can contain any characters, except both quote types.
+        let system_quote = match system_id {
+            Some(id) if id.contains('"') => {
+                if id.contains('\'') {
+                    return Err(invalid_data_error("invalid doctype system id"));
+                }
+                "'"
+            }
+            _ => "\"",
+        };
+
+        if self.state != State::Empty {
+            self.write_new_line()?;
+        }
+
+        self.fmt_writer.write_raw("<!DOCTYPE ")?;
+        self.fmt_writer.write_raw(name)?;
+
+        if let Some(public_id) = public_id {
+            self.fmt_writer.write_raw(" PUBLIC \"")?;
+            self.fmt_writer.write_raw(public_id)?;
+            self.fmt_writer.write_raw("\"")?;
+        } else if system_id.is_some() {
+            self.fmt_writer.write_raw(" SYSTEM")?;
+        }
+
+        if let Some(system_id) = system_id {
+            self.fmt_writer.write_raw(" ")?;
+            self.fmt_writer.write_raw(system_quote)?;
+            self.fmt_writer.write_raw(system_id)?;
+            self.fmt_writer.write_raw(system_quote)?;
+        }
+
+        if let Some(subset) = subset {
+            self.fmt_writer.write_raw(" [")?;
+            subset(self)?;
+            self.fmt_writer.write_raw("]")?;
+        }
+
+        self.fmt_writer.write_raw(">")?;
+
+        self.state = State::Document;
+        self.doctype_written = true;
+
+        Ok(())
+    }
+
+    /// Writes a comment string.
+    pub fn write_comment(&mut self, text: &str) -> Result<()> {
+        self.write_comment_fmt(format_args!("{}", text))
+    }
+
+    /// Writes a comment from any object that implements `fmt::Display`.
+    ///
+    /// See [`write_comment_fmt()`] for details.
+    ///
+    /// [`write_comment_fmt()`]: struct.XmlWriter.html#method.write_comment_fmt
+    pub fn write_comment_display<T: Display + ?Sized>(&mut self, text: &T) -> Result<()> {
+        self.write_comment_fmt(format_args!("{}", text))
+    }
+
+    /// Writes a comment built by a closure.
+    ///
+    /// The closure writes the comment text into a scratch buffer, which is reused between calls.
+    ///
+    /// See [`write_comment_fmt()`] for details.
+    ///
+    /// [`write_comment_fmt()`]: struct.XmlWriter.html#method.write_comment_fmt
+    ///
+    /// # Errors
+    ///
+    /// - When the closure returns an error. Nothing is written in this case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::fmt::Write;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_comment_from(|buf| {
+    ///         write!(buf, "generated from {} files", 3)
+    ///     })?;
+    ///     w.start_element("svg")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<!--generated from 3 files-->\n<svg/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_comment_from<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut String) -> fmt::Result,
+    {
+        let mut scratch = core::mem::replace(&mut self.scratch, String::new());
+        scratch.clear();
+        let result = match f(&mut scratch) {
+            Ok(()) => self.write_comment_fmt(format_args!("{}", scratch)),
+            Err(_) => Err(formatter_error()),
+        };
+        self.scratch = scratch;
+        result
+    }
+
+    /// Writes a formatted comment.
+    ///
+    /// Forbidden double hyphens, a trailing hyphen and a leading `>`
+    /// will be separated with a space.
+    ///
+    /// When whitespaces are preserved, the comment is written right after the previous node,
+    /// without a new line and indention, since they would become a part of the content.
+    ///
+    /// # Panics
+    ///
+    /// - When called right after `start_element()` or `write_attribute()`
+    ///   and `Options::open_element_on_comment` is disabled.
+    #[inline(never)]
+    pub fn write_comment_fmt(&mut self, fmt: fmt::Arguments) -> Result<()> {
+        if self.state == State::Attributes && !self.opt.open_element_on_comment {
+            panic!("comment cannot be written before the element attributes are finished");
+        }
+
+        let is_sibling = self.state == State::Document;
+
+        if self.state == State::Attributes {
+            self.write_open_element()?;
+        }
+
+        if self.state != State::Empty {
+            self.write_new_line()?;
+        }
+
+        if is_sibling {
+            self.write_sibling_separator()?;
+        }
+
+        self.write_node_indent()?;
+
+        // <!--text-->
+        self.fmt_writer.write_raw("<!--")?;
+        self.fmt_writer.comment_last = None;
+        match self.opt.comment_wrap_width {
+            Some(width) if self.opt.indent != Indent::None && !self.preserve_whitespaces => {
+                // Wrapping requires the whole text to be known beforehand.
+                let text = fmt.to_string();
+                if self.node_indent_width() + 7 + text.chars().count() > width {
+                    self.write_wrapped_comment(&text, width)?;
+                } else {
+                    self.fmt_writer.write_comment_escaped(&text)?;
+                }
+            }
+            _ => {
+                self.fmt_writer.escape = Some(Escape::Comment);
+                self.fmt_writer
+                    .write_fmt(fmt)
+                    .map_err(|_| self.fmt_writer.take_err())?;
+            }
+        }
+        // A comment must not end with `--->`.
+        if self.fmt_writer.comment_last == Some('-') {
+            self.fmt_writer.write_raw(" ")?;
+        }
+        self.fmt_writer.write_raw("-->")?;
+
+        if self.state == State::Attributes {
+            self.depth_stack.push(DepthData {
+                element_name: None,
+                has_children: false,
+                inline_start: None,
+                nowrap: false,
+            });
+        }
+
+        self.state = State::Document;
+
+        Ok(())
+    }
+
+    /// Writes a processing instruction.
+    ///
+    /// `<?target data?>`
+    ///
+    /// The `data` is written as is. An empty `data` produces `<?target?>`.
+    ///
+    /// # Panics
+    ///
+    /// - When `target` is empty or is `xml` in any case, which is reserved for the declaration.
+    /// - When `data` contains `?>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_processing_instruction("xml-stylesheet", "href=\"style.css\"")?;
+    ///     w.start_element("svg")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<?xml-stylesheet href=\"style.css\"?>\n<svg/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_processing_instruction(&mut self, target: &str, data: &str) -> Result<()> {
+        if data.contains("?>") {
+            panic!("processing instruction data cannot contain '?>'");
+        }
+
+        self.start_processing_instruction(target)?;
+        if !data.is_empty() {
+            self.fmt_writer.write_raw(" ")?;
+            self.fmt_writer.write_raw(data)?;
+        }
+        self.fmt_writer.write_raw("?>")
+    }
+
+    /// Writes an `xml-model` processing instruction, which associates a schema with the document.
+    ///
+    /// `<?xml-model href="..." type="..." schematypens="..."?>`
+    ///
+    /// Pseudo-attributes are written in the order above and escaped like attribute values.
+    /// `None` values are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_xml_model("book.rnc", Some("application/relax-ng-compact-syntax"), None)?;
+    ///     w.start_element("book")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<?xml-model href=\"book.rnc\" type=\"application/relax-ng-compact-syntax\"?>\n<book/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_xml_model(
+        &mut self,
+        href: &str,
+        type_: Option<&str>,
+        schematypens: Option<&str>,
+    ) -> Result<()> {
+        self.start_processing_instruction("xml-model")?;
+        self.write_pseudo_attribute("href", href)?;
+        if let Some(type_) = type_ {
+            self.write_pseudo_attribute("type", type_)?;
+        }
+        if let Some(schematypens) = schematypens {
+            self.write_pseudo_attribute("schematypens", schematypens)?;
+        }
+        self.fmt_writer.write_raw("?>")
+    }
+
+    /// Writes an `xml-stylesheet` processing instruction, which associates a stylesheet
+    /// with the document.
+    ///
+    /// `<?xml-stylesheet href="..." type="..."?>`
+    ///
+    /// Pseudo-attributes are quoted and escaped like attribute values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.write_xml_stylesheet("style.css", "text/css")?;
+    ///     w.start_element("svg")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<?xml-stylesheet href=\"style.css\" type=\"text/css\"?>\n<svg/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_xml_stylesheet(&mut self, href: &str, type_: &str) -> Result<()> {
+        self.start_processing_instruction("xml-stylesheet")?;
+        self.write_pseudo_attribute("href", href)?;
+        self.write_pseudo_attribute("type", type_)?;
+        self.fmt_writer.write_raw("?>")
+    }
+
+    /// Writes a downlevel-hidden conditional comment, like `<!--[if IE]>...<![endif]-->`.
+    ///
+    /// The closure writes the content. Elements left open by the closure are closed
+    /// before the comment end.
+    ///
+    /// # Panics
+    ///
+    /// - When `condition` contains `--` or `]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("head")?;
+    ///     w.write_conditional_comment("lt IE 9", |w| {
+    ///         w.start_element("script")?;
+    ///         w.write_attribute("src", "html5shiv.js")?;
+    ///         w.end_element()
+    ///     })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<head>
+    ///     <!--[if lt IE 9]>
+    ///     <script src=\"html5shiv.js\"/>
+    ///     <![endif]-->
+    /// </head>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_conditional_comment<F>(&mut self, condition: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        if condition.contains("--") || condition.contains(']') {
+            panic!("invalid conditional comment condition");
+        }
+
+        self.begin_node()?;
+        self.fmt_writer.write_raw("<!--[if ")?;
+        self.fmt_writer.write_raw(condition)?;
+        self.fmt_writer.write_raw("]>")?;
+        self.state = State::Document;
+
+        let depth = self.depth_stack.len();
+        f(self)?;
+        while self.depth_stack.len() > depth {
+            self.end_element()?;
+        }
+
+        self.begin_node()?;
+        self.fmt_writer.write_raw("<![endif]-->")?;
+        self.state = State::Document;
+
+        Ok(())
+    }
+
+    // Writes the `<?target` part, positioned like any other node.
+    fn start_processing_instruction(&mut self, target: &str) -> Result<()> {
+        if target.is_empty() || target.eq_ignore_ascii_case("xml") {
+            panic!("invalid processing instruction target");
+        }
+
+        self.begin_node()?;
+
+        self.fmt_writer.write_raw("<?")?;
+        self.fmt_writer.write_raw(target)?;
+
+        self.state = State::Document;
+
+        Ok(())
+    }
+
+    // Writes everything that precedes a node: closes the start tag,
+    // writes a new line and indention.
+    fn begin_node(&mut self) -> Result<()> {
+        let is_sibling = self.state == State::Document;
+
+        if self.state == State::Attributes {
+            self.write_open_element()?;
+        }
+
+        if self.state != State::Empty {
+            self.write_new_line()?;
+        }
+
+        if is_sibling {
+            self.write_sibling_separator()?;
+        }
+
+        self.write_node_indent()
+    }
+
+    // Writes a ` name="value"` pair inside a processing instruction.
+    //
+    // Unlike write_attribute_prefix(), this one always stays on the same line.
+    fn write_pseudo_attribute(&mut self, name: &str, value: &str) -> Result<()> {
+        self.fmt_writer.write_raw(" ")?;
+        self.fmt_writer.write_raw(name)?;
+        self.fmt_writer.write_raw("=")?;
+        self.write_quote()?;
+        self.fmt_writer.write_escaped(value, true)?;
+        self.write_quote()
+    }
+
+    /// Starts writing a new element with attributes written by [`WriteAttributes`].
+    ///
+    /// [`WriteAttributes`]: trait.WriteAttributes.html
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    ///
+    /// # #[cfg(feature = "std")]
+    /// impl WriteAttributes for Point {
+    ///     fn write_attributes<W: io::Write>(&self, w: &mut XmlWriter<W>) -> io::Result<()> {
+    ///         w.write_attribute("x", &self.x)?;
+    ///         w.write_attribute("y", &self.y)
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element_with("point", &Point { x: 1, y: 2 })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<point x=\"1\" y=\"2\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn start_element_with<A>(&mut self, name: &str, attrs: &A) -> Result<()>
+    where
+        A: WriteAttributes + ?Sized,
+    {
+        self.start_element(name)?;
+        attrs.write_attributes(self)
+    }
+
+    /// Starts writing a new element, which attributes are written on the same line.
+    ///
+    /// Same as `start_element()`, but ignores `Options::attributes_indent`
+    /// for this element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options {
+    ///         attributes_indent: Indent::Spaces(2),
+    ///         ..Options::default()
+    ///     };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element_nowrap("rect")?;
+    ///     w.write_attribute("x", &10)?;
+    ///     w.write_attribute("y", &20)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<rect x=\"10\" y=\"20\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn start_element_nowrap(&mut self, name: &str) -> Result<()> {
+        self.start_element(name)?;
+        if let Some(depth) = self.depth_stack.last_mut() {
+            depth.nowrap = true;
+        }
+
+        Ok(())
+    }
+
+    /// Starts writing a new element.
+    ///
+    /// This method writes only the `<tag-name` part.
+    #[inline(never)]
+    pub fn start_element(&mut self, name: &str) -> Result<()> {
+        let is_sibling = self.state == State::Document;
+
+        // The parent start tag can be reopened only when closing it writes nothing but `>`.
+        let mut start = None;
+        if self.state == State::Attributes {
+            if self.attribute_children.is_empty() && !self.fmt_writer.capturing {
+                start = self.element_start_snapshot(true);
+            }
+
+            self.write_open_element()?;
+        }
+
+        if start.is_none() {
+            start = self.element_start_snapshot(false);
+        }
+
+        if self.state != State::Empty {
+            self.write_new_line()?;
+        }
+
+        if is_sibling {
+            self.write_sibling_separator()?;
+        }
+
+        let is_root = self.depth_stack.is_empty();
+        if !self.preserve_whitespaces && (self.opt.indent_root || !is_root) {
+            self.write_node_indent()?;
+        }
+
+        let (line, column) = (self.fmt_writer.line, self.fmt_writer.column);
+        self.fmt_writer.write_raw("<")?;
+        self.fmt_writer.write_raw(name)?;
+
+        self.element_start = start.map(|start| ElementStart {
+            name_end: self.fmt_writer.offset,
+            ..start
+        });
+
+        if self.attribute_order.is_some()
+            || self.opt.namespace_decls_first
+            || self.is_aligning_attributes()
+        {
+            self.fmt_writer.capturing = true;
+        }
+
+        if let Some(ref mut observer) = self.observer.0 {
+            observer.on_start_element(name, line, column);
+        }
+
+        self.root_written = true;
+        self.depth_stack.push(DepthData {
+            element_name: Some(name.to_string()),
+            has_children: false,
+            inline_start: None,
+            nowrap: false,
+        });
+
+        self.state = State::Attributes;
+
+        Ok(())
+    }
+
+    fn element_start_snapshot(&self, parent_opened: bool) -> Option<ElementStart> {
+        // Trailing whitespaces may be dropped later, so the output size is unknown.
+        if !self.fmt_writer.pending_whitespace.is_empty() {
+            return None;
+        }
+
+        Some(ElementStart {
+            state: self.state,
+            root_written: self.root_written,
+            text_end: self.text_end,
+            new_line_end: self.new_line_end,
+            node_offsets_len: self.node_offsets.len(),
+            line: self.fmt_writer.line,
+            column: self.fmt_writer.column,
+            offset: self.fmt_writer.offset,
+            text_brackets: self.fmt_writer.text_brackets,
+            name_end: 0,
+            parent_opened,
+        })
+    }
+
+    /// Writes an empty element marked as nil, like `<name xsi:nil="true"/>`.
+    ///
+    /// The `xsi` namespace must be declared by the caller.
+    pub fn write_nil_element(&mut self, name: &str) -> Result<()> {
+        self.start_element(name)?;
+        self.write_attribute_unescaped("xsi:nil", "true")?;
+        self.end_element()
+    }
+
+    /// Starts writing a new element with an optional namespace prefix.
+    ///
+    /// Writes `<prefix:local` or just `<local`.
+    ///
+    /// # Panics
+    ///
+    /// - When the prefix wasn't declared via `with_namespace()` and `Options::strict` is enabled.
+    ///   The `xml` prefix is always declared.
+    pub fn start_element_ns(&mut self, prefix: Option<&str>, local: &str) -> Result<()> {
+        match prefix {
+            Some(prefix) => {
+                self.check_namespace_prefix(prefix);
+                self.start_element(&prefixed_name(prefix, local))
+            }
+            None => self.start_element(local),
+        }
+    }
+
+    /// Writes an attribute with a namespace prefix, like `prefix:local="value"`.
+    ///
+    /// Accepts the same values as [`write_attribute()`].
+    ///
+    /// [`write_attribute()`]: struct.XmlWriter.html#method.write_attribute
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    /// - When the prefix wasn't declared via `with_namespace()` and `Options::strict` is enabled.
+    ///   The `xml` prefix is always declared.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.with_namespace("xlink", "http://www.w3.org/1999/xlink", |w| {
+    ///         w.start_element("use")?;
+    ///         w.write_attribute_prefixed("xlink", "href", "#rect1")?;
+    ///         w.end_element()
+    ///     })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg xmlns:xlink=\"http://www.w3.org/1999/xlink\">
+    ///     <use xlink:href=\"#rect1\"/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_prefixed<V: IntoAttrValue>(
+        &mut self,
+        prefix: &str,
+        local: &str,
+        value: V,
+    ) -> Result<()> {
+        self.check_namespace_prefix(prefix);
+        self.write_attribute(&prefixed_name(prefix, local), value)
+    }
+
+    fn check_namespace_prefix(&self, prefix: &str) {
+        if self.opt.strict && prefix != "xml" && !self.namespaces.iter().any(|p| p == prefix) {
+            panic!("undeclared namespace prefix");
+        }
+    }
+
+    /// Declares a namespace prefix on the current element and calls the closure
+    /// with the prefix in scope.
+    ///
+    /// Writes the `xmlns:prefix="uri"` attribute. Elements started via
+    /// `start_element_ns()` inside the closure can use the prefix.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.with_namespace("xlink", "http://www.w3.org/1999/xlink", |w| {
+    ///         w.start_element_ns(Some("xlink"), "a")?;
+    ///         w.end_element()
+    ///     })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg xmlns:xlink=\"http://www.w3.org/1999/xlink\">
+    ///     <xlink:a/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_namespace<F>(&mut self, prefix: &str, uri: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        self.write_attribute_fmt(&prefixed_name("xmlns", prefix), format_args!("{}", uri))?;
+        self.namespaces.push(prefix.to_string());
+        let result = f(self);
+        self.namespaces.pop();
+        result
+    }
+
+    /// Writes an element only when the closure writes its content.
+    ///
+    /// Starts an element and calls the closure, which returns whether it wrote anything.
+    /// When it returns `false` or doesn't write anything, the element is omitted entirely.
+    /// Elements opened inside the closure are closed automatically.
+    ///
+    /// The element output is held in memory until the closure completes.
+    /// `XmlEvents` are reported for omitted nodes as well.
+    ///
+    /// # Errors
+    ///
+    /// - When the closure returns an error. The element is omitted in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When the closure closes elements it didn't open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("report")?;
+    ///     for errors in &[vec![], vec!["bad input"]] {
+    ///         w.write_element_if_nonempty("errors", |w| {
+    ///             for error in errors {
+    ///                 w.start_element("error")?;
+    ///                 w.write_text(error)?;
+    ///                 w.end_element()?;
+    ///             }
+    ///             Ok(!errors.is_empty())
+    ///         })?;
+    ///     }
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<report>
+    ///     <errors>
+    ///         <error>
+    ///             bad input
+    ///         </error>
+    ///     </errors>
+    /// </report>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_element_if_nonempty<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<bool>,
+    {
+        // Write out the reordered attributes of the parent element,
+        // since they cannot be restored when the element is omitted.
+        self.flush_attributes()?;
+
+        let state = self.state;
+        let root_written = self.root_written;
+        // Elements below this depth cannot be closed by the closure,
+        // so only the parent can change.
+        let depth = self.depth_stack.len();
+        let parent_has_children = self.depth_stack.last().map(|d| d.has_children);
+        let attribute_children = self.attribute_children.clone();
+        let text_end = self.text_end;
+        let new_line_end = self.new_line_end;
+        let element_start = self.element_start;
+        let node_offsets_len = self.node_offsets.len();
+        let fmt_state = (
+            self.fmt_writer.line,
+            self.fmt_writer.column,
+            self.fmt_writer.offset,
+            self.fmt_writer.text_brackets,
+            self.fmt_writer.comment_last,
+            self.fmt_writer.pending_whitespace.clone(),
+        );
+
+        let buffer_start = self.fmt_writer.buffer.len();
+        self.fmt_writer.buffering += 1;
+
+        let result = self.write_element_if_nonempty_impl(name, f);
+
+        self.fmt_writer.buffering -= 1;
+
+        match result {
+            Ok(true) => {
+                if self.fmt_writer.buffering == 0 {
+                    let buffer = core::mem::replace(&mut self.fmt_writer.buffer, String::new());
+                    self.fmt_writer.write_to_writer(&buffer)?;
+                    // Keep the buffer capacity.
+                    self.fmt_writer.buffer = buffer;
+                    self.fmt_writer.buffer.clear();
+                }
+
+                Ok(())
+            }
+            Ok(false) | Err(_) => {
+                self.fmt_writer.buffer.truncate(buffer_start);
+                self.state = state;
+                self.root_written = root_written;
+                self.depth_stack.truncate(depth);
+                if let (Some(parent), Some(has_children)) =
+                    (self.depth_stack.last_mut(), parent_has_children)
+                {
+                    parent.has_children = has_children;
+                }
+                self.attribute_children = attribute_children;
+                self.text_end = text_end;
+                self.new_line_end = new_line_end;
+                self.element_start = element_start;
+                self.node_offsets.truncate(node_offsets_len);
+
+                let fmt_writer = &mut self.fmt_writer;
+                fmt_writer.line = fmt_state.0;
+                fmt_writer.column = fmt_state.1;
+                fmt_writer.offset = fmt_state.2;
+                fmt_writer.text_brackets = fmt_state.3;
+                fmt_writer.comment_last = fmt_state.4;
+                fmt_writer.pending_whitespace = fmt_state.5;
+
+                result.map(|_| ())
+            }
+        }
+    }
+
+    fn write_element_if_nonempty_impl<F>(&mut self, name: &str, f: F) -> Result<bool>
+    where
+        F: FnOnce(&mut Self) -> Result<bool>,
+    {
+        let depth = self.depth_stack.len();
+        self.start_element(name)?;
+        let offset = self.fmt_writer.offset;
+        let attribute_children = self.attribute_children.len();
+
+        let written = f(self)?;
+        if self.depth_stack.len() <= depth {
+            panic!("the closure closed elements it didn't open");
+        }
+
+        let written = written
+            && (self.fmt_writer.offset != offset
+                || self.attribute_children.len() != attribute_children);
+
+        while self.depth_stack.len() > depth {
+            self.end_element()?;
+        }
+
+        Ok(written)
+    }
+
+    /// Writes an element, with its attributes and children written by separate closures.
+    ///
+    /// The `attrs` closure gets an [`AttrWriter`], which can write only attributes.
+    /// The `body` closure is called after the attributes are flushed and writes the content.
+    /// All elements opened by `body` and the element itself are closed at the end.
+    ///
+    /// [`AttrWriter`]: struct.AttrWriter.html
+    ///
+    /// # Errors
+    ///
+    /// - When any closure returns an error. The element is not closed in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When `body` closes elements it didn't open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.element_scope("svg", |a| a.write_attribute("width", &100), |w| {
+    ///         w.start_element("rect")?;
+    ///         w.write_attribute("fill", "red")
+    ///     })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg width=\"100\">
+    ///     <rect fill=\"red\"/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn element_scope<A, B>(&mut self, name: &str, attrs: A, body: B) -> Result<()>
+    where
+        A: FnOnce(&mut AttrWriter<W>) -> Result<()>,
+        B: FnOnce(&mut Self) -> Result<()>,
+    {
+        let depth = self.depth_stack.len();
+        self.start_element(name)?;
+        attrs(&mut AttrWriter { w: self })?;
+        self.flush_attributes()?;
+
+        body(self)?;
+        if self.depth_stack.len() <= depth {
+            panic!("the closure closed elements it didn't open");
+        }
+
+        while self.depth_stack.len() > depth {
+            self.end_element()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes an attribute.
+    ///
+    /// Accepts a reference to any object that implements `fmt::Display`
+    /// or an `Option` of such object. The attribute is skipped on `None`.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.write_attribute("x", "5")?;
+    ///     w.write_attribute("y", &5)?;
+    ///     w.write_attribute("width", Some(10))?;
+    ///     w.write_attribute("height", None::<i32>)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<svg x=\"5\" y=\"5\" width=\"10\"/>\n",
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute<V: IntoAttrValue>(&mut self, name: &str, value: V) -> Result<()> {
+        match value.into_attr_value() {
+            Some(value) => self.write_attribute_fmt(name, format_args!("{}", value)),
+            None => {
+                self.check_attributes_allowed();
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a formatted attribute value.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("rect")?;
+    ///     w.write_attribute_fmt("fill", format_args!("url(#{})", "gradient"))?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<rect fill=\"url(#gradient)\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_attribute_fmt(&mut self, name: &str, fmt: fmt::Arguments) -> Result<()> {
+        self.check_attributes_allowed();
+
+        if let Some(predicate) = self.opt.attribute_to_element_if {
+            let value = fmt.to_string();
+            if predicate(name, &value) {
+                self.attribute_children.push((name.to_string(), value));
+                return Ok(());
+            }
+
+            return self.write_attribute_cow(name, Cow::Owned(value));
+        }
+
+        if self.opt.minimize_empty_attributes && self.opt.html_mode {
+            // The value must be known to check that it's empty.
+            return self.write_attribute_cow(name, Cow::Owned(fmt.to_string()));
+        }
+
+        self.write_attribute_prefix(name)?;
+        self.fmt_writer.escape = Some(Escape::AttributeValue);
+        self.fmt_writer
+            .write_fmt(fmt)
+            .map_err(|_| self.fmt_writer.take_err())?;
+        self.write_quote()
+    }
+
+    /// Writes an attribute with a borrowed or an owned string value.
+    ///
+    /// Unlike [`write_attribute()`], the string is escaped directly,
+    /// without going through the formatting machinery.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// [`write_attribute()`]: struct.XmlWriter.html#method.write_attribute
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    #[inline(never)]
+    pub fn write_attribute_cow(&mut self, name: &str, value: Cow<str>) -> Result<()> {
+        self.check_attributes_allowed();
+
+        if value.is_empty() && self.opt.minimize_empty_attributes && self.opt.html_mode {
+            return self.write_attribute_name(name);
+        }
+
+        self.write_attribute_prefix(name)?;
+        self.fmt_writer.write_escaped(&value, true)?;
+        self.write_quote()
+    }
+
+    /// Writes attributes from name-value pairs, like a map.
+    ///
+    /// Attributes are written in the iteration order, without any sorting.
+    ///
+    /// Any occurrence of `&<>"'` in the values will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::collections::BTreeMap;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut attrs = BTreeMap::new();
+    ///     attrs.insert("y", 2);
+    ///     attrs.insert("x", 1);
+    ///
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("point")?;
+    ///     w.write_attributes_map(&attrs)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<point x=\"1\" y=\"2\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attributes_map<I, K, V>(&mut self, attrs: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Display,
+    {
+        self.check_attributes_allowed();
+
+        for (name, value) in attrs {
+            self.write_attribute_fmt(name.as_ref(), format_args!("{}", value))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes an attribute with a value produced by a chars iterator.
+    ///
+    /// Chars are escaped and written as they are produced,
+    /// without collecting them into a `String` first.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.write_attribute_chars("id", "svg1".chars().rev())?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<svg id=\"1gvs\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_chars<I>(&mut self, name: &str, chars: I) -> Result<()>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+        let mut buf = [0; 4];
+        for c in chars {
+            self.fmt_writer
+                .write_escaped(c.encode_utf8(&mut buf), true)?;
+        }
+        self.write_quote()
+    }
+
+    /// Writes an attribute with a value streamed from a reader.
+    ///
+    /// The value is read and escaped in chunks, without holding it in memory.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the reader produces invalid UTF-8.
+    /// - Any error returned by the reader.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("a")?;
+    ///     w.write_attribute_from_reader("href", &mut "data:,a&b".as_bytes())?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<a href=\"data:,a&amp;b\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_attribute_from_reader<R>(&mut self, name: &str, r: &mut R) -> Result<()>
+    where
+        R: io::Read + ?Sized,
+    {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+
+        let mut buf = [0; 4096];
+        // The number of bytes in `buf`, including an incomplete UTF-8 sequence
+        // left from the previous chunk.
+        let mut len = 0;
+        loop {
+            let n = match r.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            len += n;
+
+            let valid = match std::str::from_utf8(&buf[..len]) {
+                Ok(s) => s.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => return Err(invalid_data_error("stream did not contain valid UTF-8")),
+            };
+
+            let s = std::str::from_utf8(&buf[..valid]).expect("checked above");
+            self.fmt_writer.write_escaped(s, true)?;
+
+            // Move the incomplete sequence to the start.
+            for i in valid..len {
+                buf[i - valid] = buf[i];
+            }
+            len -= valid;
+        }
+
+        if len != 0 {
+            return Err(invalid_data_error("stream did not contain valid UTF-8"));
+        }
+
+        self.write_quote()
+    }
+
+    /// Writes an attribute with a value produced by [`AsXmlAttr`].
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// [`AsXmlAttr`]: trait.AsXmlAttr.html
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::borrow::Cow;
+    /// use std::io;
+    ///
+    /// enum LineCap {
+    ///     Butt,
+    ///     Round,
+    /// }
+    ///
+    /// impl AsXmlAttr for LineCap {
+    ///     fn as_xml_attr(&self) -> Cow<'_, str> {
+    ///         match self {
+    ///             LineCap::Butt => Cow::Borrowed("butt"),
+    ///             LineCap::Round => Cow::Borrowed("round"),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("path")?;
+    ///     w.write_attribute_as("stroke-linecap", &LineCap::Round)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<path stroke-linecap=\"round\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_as<V: AsXmlAttr + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &V,
+    ) -> Result<()> {
+        self.write_attribute_cow(name, value.as_xml_attr())
+    }
+
+    /// Writes an attribute with a URI value, like `href` or `src`.
+    ///
+    /// Characters that are not allowed in a URI, like spaces or non-ASCII characters,
+    /// are percent-encoded as UTF-8 bytes first. Existing percent-encoded sequences
+    /// are kept as is.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("image")?;
+    ///     w.write_uri_attribute("href", "my image.png")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<image href=\"my%20image.png\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_uri_attribute(&mut self, name: &str, uri: &str) -> Result<()> {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+
+        let mut part_start_pos = 0;
+        for (pos, c) in uri.char_indices() {
+            if is_uri_char(c) {
+                continue;
+            }
+
+            self.fmt_writer
+                .write_escaped(&uri[part_start_pos..pos], true)?;
+
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                let encoded = [
+                    b'%',
+                    HEX_DIGITS[(byte >> 4) as usize],
+                    HEX_DIGITS[(byte & 0xF) as usize],
+                ];
+                self.fmt_writer.write_raw(
+                    core::str::from_utf8(&encoded).expect("percent-encoding is ASCII"),
+                )?;
+            }
+
+            part_start_pos = pos + c.len_utf8();
+        }
+        self.fmt_writer
+            .write_escaped(&uri[part_start_pos..], true)?;
+
+        self.write_quote()
+    }
+
+    /// Writes an `xml:lang` attribute.
+    ///
+    /// The language tag must consist of subtags of 1 to 8 ASCII letters or digits
+    /// separated by `-`, with the first subtag having only letters, like `en-US`.
+    /// This is only a basic BCP 47 shape check, the subtags are not validated against a registry.
+    /// An empty value is allowed, which means that the language is unknown.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the language tag is malformed and `Options::strict` is enabled.
+    ///   Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("text")?;
+    ///     w.write_xml_lang("en-US")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<text xml:lang=\"en-US\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_xml_lang(&mut self, lang: &str) -> Result<()> {
+        if self.opt.strict && !is_language_tag(lang) {
+            return Err(invalid_data_error("invalid xml:lang value"));
+        }
+
+        self.write_attribute("xml:lang", lang)
+    }
+
+    /// Writes an `xml:base` attribute.
+    ///
+    /// See [`write_uri_attribute()`] for details.
+    ///
+    /// [`write_uri_attribute()`]: struct.XmlWriter.html#method.write_uri_attribute
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the URI contains characters that are not allowed in a URI
+    ///   and `Options::strict` is enabled, instead of percent-encoding them.
+    ///   Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    pub fn write_xml_base(&mut self, uri: &str) -> Result<()> {
+        if self.opt.strict && !uri.chars().all(is_uri_char) {
+            return Err(invalid_data_error("invalid xml:base value"));
+        }
+
+        self.write_uri_attribute("xml:base", uri)
+    }
+
+    /// Writes an attribute value from bytes that may not be valid UTF-8.
+    ///
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`,
+    /// just like `String::from_utf8_lossy()` does.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.write_attribute_lossy("id", b"a\xFFb")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<svg id=\"a\u{FFFD}b\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_lossy(&mut self, name: &str, value: &[u8]) -> Result<()> {
+        self.write_attribute_cow(name, String::from_utf8_lossy(value))
+    }
+
+    /// Writes a timestamp attribute as an ISO 8601 (RFC 3339) UTC date and time,
+    /// like `1970-01-01T00:00:00Z`.
+    ///
+    /// Fractional seconds are written only when present, without trailing zeros.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("event")?;
+    ///     w.write_datetime_attribute("time", UNIX_EPOCH + Duration::from_millis(1_500))?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<event time=\"1970-01-01T00:00:01.5Z\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    #[inline(never)]
+    pub fn write_datetime_attribute(&mut self, name: &str, t: SystemTime) -> Result<()> {
+        let (secs, nanos) = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                let d = e.duration();
+                let secs = -(d.as_secs() as i64);
+                match d.subsec_nanos() {
+                    0 => (secs, 0),
+                    n => (secs - 1, 1_000_000_000 - n),
+                }
+            }
+        };
+
+        // Floor division, so that times before the epoch are handled too.
+        let days = if secs >= 0 { secs } else { secs - 86_399 } / 86_400;
+        let day_secs = secs - days * 86_400;
+        let (year, month, day) = civil_from_days(days);
+        let (hour, minute, second) = (day_secs / 3600, day_secs / 60 % 60, day_secs % 60);
+
+        if nanos == 0 {
+            self.write_attribute_fmt(
+                name,
+                format_args!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                    year, month, day, hour, minute, second
+                ),
+            )
+        } else {
+            // Strip trailing zeros.
+            let mut frac = nanos;
+            let mut width = 9;
+            while frac % 10 == 0 {
+                frac /= 10;
+                width -= 1;
+            }
+
+            self.write_attribute_fmt(
+                name,
+                format_args!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:0width$}Z",
+                    year,
+                    month,
+                    day,
+                    hour,
+                    minute,
+                    second,
+                    frac,
+                    width = width
+                ),
+            )
+        }
+    }
+
+    /// Writes a fraction as a percentage attribute, like `50%`.
+    ///
+    /// The fraction is multiplied by 100 and clamped to the `0..=100` range.
+    /// `NaN` is written as `0%`. At most two decimal digits are written,
+    /// without trailing zeros.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("stop")?;
+    ///     w.write_percentage_attribute("offset", 0.125)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<stop offset=\"12.5%\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_percentage_attribute(&mut self, name: &str, fraction: f64) -> Result<()> {
+        self.check_attributes_allowed();
+
+        // Also handles NaN and `-0`.
+        let percent = fraction * 100.0;
+        let percent = if percent > 0.0 {
+            percent.min(100.0)
+        } else {
+            0.0
+        };
+
+        let mut scratch = core::mem::replace(&mut self.scratch, String::new());
+        scratch.clear();
+        let result = match scratch.write_fmt(format_args!("{:.2}", percent)) {
+            Ok(()) => {
+                let number_len = scratch.trim_end_matches('0').trim_end_matches('.').len();
+                scratch.truncate(number_len);
+                scratch.push('%');
+                self.write_attribute_unescaped(name, &scratch)
+            }
+            Err(_) => Err(formatter_error()),
+        };
+        self.scratch = scratch;
+        result
+    }
+
+    /// Writes an attribute with a space-separated list of tokens, like `class="a b c"`.
+    ///
+    /// An empty list produces an empty attribute value.
+    ///
+    /// Any occurrence of `&<>"'` in the tokens will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("div")?;
+    ///     w.write_token_list_attribute("class", &["foo", "bar"])?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<div class=\"foo bar\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_token_list_attribute<I, T>(&mut self, name: &str, tokens: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: Display,
+    {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+        for (i, token) in tokens.into_iter().enumerate() {
+            if i > 0 {
+                self.fmt_writer.write_raw(" ")?;
+            }
+
+            self.fmt_writer.escape = Some(Escape::AttributeValue);
+            self.fmt_writer
+                .write_fmt(format_args!("{}", token))
+                .map_err(|_| self.fmt_writer.take_err())?;
+        }
+        self.write_quote()
+    }
+
+    /// Writes an attribute with a value computed by a closure.
+    ///
+    /// The closure is always called, but only after the writer state was checked.
+    /// Evaluation is not deferred any further: to skip the attribute,
+    /// the closure returns an empty string and the attribute is not written at all.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("rect")?;
+    ///     w.write_attribute_lazy("id", || "rect1".to_string())?;
+    ///     w.write_attribute_lazy("class", String::new)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<rect id=\"rect1\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_lazy<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        self.check_attributes_allowed();
+
+        let value = f();
+        if value.is_empty() {
+            return Ok(());
+        }
+
+        self.write_attribute_cow(name, Cow::Owned(value))
+    }
+
+    /// Writes an attribute value built by a closure.
+    ///
+    /// The closure writes the value into a scratch buffer, which is reused between calls.
+    /// The value is escaped afterwards.
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// # Errors
+    ///
+    /// - When the closure returns an error.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::fmt::Write;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("path")?;
+    ///     w.write_attribute_from("d", |buf| {
+    ///         for (x, y) in &[(10, 20), (30, 40)] {
+    ///             write!(buf, "{} {} ", x, y)?;
+    ///         }
+    ///         Ok(())
+    ///     })?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<path d=\"10 20 30 40 \"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_attribute_from<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut String) -> fmt::Result,
+    {
+        self.check_attributes_allowed();
+
+        let mut scratch = core::mem::replace(&mut self.scratch, String::new());
+        scratch.clear();
+        let result = match f(&mut scratch) {
+            Ok(()) => self.write_attribute_fmt(name, format_args!("{}", scratch)),
+            Err(_) => Err(formatter_error()),
+        };
+        self.scratch = scratch;
+        result
+    }
+
+    /// Writes a raw attribute value, without performing escaping.
+    ///
+    /// Closure provides a mutable reference to the writer.
+    ///
+    /// **Warning:** this method is an escape hatch for cases when you need to write
+    /// a lot of data very fast, and as such does no validity checks whatsoever on the
+    /// written value.
+    ///
+    /// Since the value bypasses the `XmlWriter`, it cannot be used while the output
+    /// is held back, like with `Options::attributes_indent` alignment or inside
+    /// [`write_element_if_nonempty()`]. Use [`write_attribute_raw_checked()`] instead.
+    /// The current element cannot be canceled afterwards either.
+    /// With `Options::omit_encoding_when_ascii`, the declaration encoding is kept.
+    ///
+    /// [`write_element_if_nonempty()`]: struct.XmlWriter.html#method.write_element_if_nonempty
+    /// [`write_attribute_raw_checked()`]: struct.XmlWriter.html#method.write_attribute_raw_checked
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    /// - When the output is held back.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io::{self, Write};
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("path")?;
+    ///     w.write_attribute_raw("d", |writer| writer.write_all(b"M 10 20 L 30 40"))?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<path d=\"M 10 20 L 30 40\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_attribute_raw<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut W) -> Result<()>,
+    {
+        self.check_attributes_allowed();
+
+        // Raw data is not tracked for non-ASCII characters, so the encoding is kept.
+        if self.fmt_writer.buffering == 1 && !self.fmt_writer.capturing {
+            self.fmt_writer.release_encoding()?;
+        }
+
+        if self.fmt_writer.capturing || self.fmt_writer.buffering != 0 {
+            panic!("write_attribute_raw() cannot be used while the output is held back");
+        }
+
+        self.write_attribute_prefix(name)?;
+        self.fmt_writer.flush_pending_whitespace()?;
+        // The value size is unknown, so the element cannot be truncated anymore.
+        self.element_start = None;
+        f(&mut self.fmt_writer.writer)?;
+        self.write_quote()
+    }
+
+    /// Writes a raw attribute value, without performing escaping.
+    ///
+    /// Like [`write_attribute_raw()`], but the closure provides a [`RawWriter`],
+    /// which passes data through the `XmlWriter`. So it works with all options
+    /// and rejects `<` when `Options::strict` is enabled.
+    ///
+    /// [`write_attribute_raw()`]: struct.XmlWriter.html#method.write_attribute_raw
+    /// [`RawWriter`]: struct.RawWriter.html
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the value contains `<` and `Options::strict` is enabled.
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io::{self, Write};
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("path")?;
+    ///     w.write_attribute_raw_checked("d", |writer| writer.write_all(b"M 10 20 L 30 40"))?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<path d=\"M 10 20 L 30 40\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_attribute_raw_checked<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut RawWriter<W>) -> Result<()>,
+    {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+        f(&mut RawWriter {
+            fmt_writer: &mut self.fmt_writer,
+            strict: self.opt.strict,
+        })?;
+        self.write_quote()
+    }
+
+    /// Starts writing an attribute, which value is written in chunks.
+    ///
+    /// The value is written via [`push_attribute_value()`] and the attribute
+    /// must be closed via [`end_attribute()`] before writing anything else.
+    ///
+    /// [`push_attribute_value()`]: struct.XmlWriter.html#method.push_attribute_value
+    /// [`end_attribute()`]: struct.XmlWriter.html#method.end_attribute
+    ///
+    /// # Panics
+    ///
+    /// - When called before `start_element()`.
+    /// - When called after `close_element()`.
+    /// - When another attribute is still open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("text")?;
+    ///     w.begin_attribute("style")?;
+    ///     w.push_attribute_value("color:red;")?;
+    ///     w.push_attribute_value("font-size:")?;
+    ///     w.push_attribute_value(&12)?;
+    ///     w.end_attribute()?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<text style=\"color:red;font-size:12\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn begin_attribute(&mut self, name: &str) -> Result<()> {
+        self.check_attributes_allowed();
+
+        self.write_attribute_prefix(name)?;
+        self.attribute_open = true;
+        Ok(())
+    }
+
+    /// Writes a chunk of the attribute value started by [`begin_attribute()`].
+    ///
+    /// Any occurrence of `&<>"'` in the value will be escaped.
+    ///
+    /// [`begin_attribute()`]: struct.XmlWriter.html#method.begin_attribute
+    ///
+    /// # Panics
+    ///
+    /// - When no attribute is open.
+    pub fn push_attribute_value<V: Display + ?Sized>(&mut self, value: &V) -> Result<()> {
+        if !self.attribute_open {
+            panic!("must be called after begin_attribute()");
+        }
+
+        self.fmt_writer.escape = Some(Escape::AttributeValue);
+        self.fmt_writer
+            .write_fmt(format_args!("{}", value))
+            .map_err(|_| self.fmt_writer.take_err())
+    }
+
+    /// Closes the attribute started by [`begin_attribute()`].
+    ///
+    /// [`begin_attribute()`]: struct.XmlWriter.html#method.begin_attribute
+    ///
+    /// # Panics
+    ///
+    /// - When no attribute is open.
+    pub fn end_attribute(&mut self) -> Result<()> {
+        if !self.attribute_open {
+            panic!("must be called after begin_attribute()");
+        }
+
+        self.attribute_open = false;
+        self.write_quote()
+    }
+
+    // Writes an attribute which value is known to need no escaping.
+    fn write_attribute_unescaped(&mut self, name: &str, value: &str) -> Result<()> {
+        self.write_attribute_prefix(name)?;
+        self.fmt_writer.write_raw(value)?;
+        self.write_quote()
+    }
+
+    #[inline(never)]
+    fn write_attribute_prefix(&mut self, name: &str) -> Result<()> {
+        self.write_attribute_name(name)?;
+        self.fmt_writer.write_raw("=")?;
+        self.write_quote()
+    }
+
+    // Writes an attribute separator and name.
+    fn write_attribute_name(&mut self, name: &str) -> Result<()> {
+        if self.fmt_writer.capturing {
+            let order = self.attribute_order.unwrap_or(&[]);
+            // Unknown attributes go last, in the order they were written.
+            let rank = order.iter().position(|&n| n == name).unwrap_or(order.len());
+            let start = self.fmt_writer.captured.len();
+            self.attribute_ranges.push((rank, start, start));
+        }
+
+        let nowrap = self.depth_stack.last().map_or(false, |depth| depth.nowrap);
+        if self.opt.attributes_indent == Indent::None || nowrap {
+            self.fmt_writer.write_raw(" ")?;
+        } else {
+            self.fmt_writer.write_raw(self.opt.line_ending.as_str())?;
+
+            let depth = self.node_depth();
+            if depth > 0 {
+                self.write_indent(depth - 1, self.opt.indent)?;
+            }
+
+            self.write_indent(1, self.opt.attributes_indent)?;
+        }
+
+        self.fmt_writer.write_raw(name)
+    }
+
+    /// Sets the preserve whitespaces flag.
+    ///
+    /// - If set, text nodes will be written as is.
+    /// - If not set, text nodes will be indented.
+    ///
+    /// Can be set at any moment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("html")?;
+    ///     w.start_element("p")?;
+    ///     w.write_text("text")?;
+    ///     w.end_element()?;
+    ///     w.start_element("p")?;
+    ///     w.set_preserve_whitespaces(true);
+    ///     w.write_text("text")?;
+    ///     w.end_element()?;
+    ///     w.set_preserve_whitespaces(false);
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should produce valid UTF-8"),
+    /// "<html>
+    ///     <p>
+    ///         text
+    ///     </p>
+    ///     <p>text</p>
+    /// </html>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_preserve_whitespaces(&mut self, preserve: bool) {
+        self.preserve_whitespaces = preserve;
+    }
+
+    /// Sets the `Options::use_single_quote` flag.
+    ///
+    /// Affects both the quotes around attribute values and which quote is escaped inside them.
+    ///
+    /// Can be set at any moment, except between `begin_attribute()` and `end_attribute()`.
+    ///
+    /// # Panics
+    ///
+    /// - When called between `begin_attribute()` and `end_attribute()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.write_attribute("x", "'a'")?;
+    ///     w.set_use_single_quote(true);
+    ///     w.write_attribute("y", "'b'")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<svg x=\"'a'\" y='&apos;b&apos;'/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_use_single_quote(&mut self, value: bool) {
+        self.check_attribute_closed();
+        self.opt.use_single_quote = value;
+        self.fmt_writer.use_single_quote = value;
+    }
+
+    /// Sets the `Options::enable_self_closing` flag.
+    ///
+    /// Can be set at any moment. Affects elements closed afterwards,
+    /// including the currently open ones.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options { indent: Indent::None, ..Options::default() };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("div")?;
+    ///     w.start_element("br")?;
+    ///     w.end_element()?;
+    ///     w.set_self_closing(false);
+    ///     w.start_element("span")?;
+    ///     w.end_element()?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<div><br/><span></span></div>"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_self_closing(&mut self, enabled: bool) {
+        self.opt.enable_self_closing = enabled;
+    }
+
+    /// Sets the order in which attributes are written, like the one defined by a schema.
+    ///
+    /// Attributes of each element are buffered until the start tag is closed and then written
+    /// in the order of `names`. Attributes not in the list are written last,
+    /// in the order they were written.
+    ///
+    /// Affects elements started after this call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.set_attribute_schema_order(&["id", "x", "y"]);
+    ///     w.start_element("rect")?;
+    ///     w.write_attribute("fill", "red")?;
+    ///     w.write_attribute("y", &20)?;
+    ///     w.write_attribute("x", &10)?;
+    ///     w.write_attribute("id", "rect1")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<rect id=\"rect1\" x=\"10\" y=\"20\" fill=\"red\"/>\n"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_attribute_schema_order(&mut self, names: &'static [&'static str]) {
+        self.attribute_order = Some(names);
+    }
+
+    /// Sets the depth added to the indentation of all nodes.
+    ///
+    /// Useful when the output is embedded into another, already indented, document.
+    ///
+    /// Can be set at any moment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.set_base_depth(1);
+    ///     w.start_element("svg")?;
+    ///     w.start_element("rect")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should produce valid UTF-8"),
+    /// "    <svg>
+    ///         <rect/>
+    ///     </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_base_depth(&mut self, depth: usize) {
+        self.base_depth = depth;
+    }
+
+    /// Writes a text node.
+    ///
+    /// See [`write_text_fmt()`] for details.
+    ///
+    /// [`write_text_fmt()`]: struct.XmlWriter.html#method.write_text_fmt
+    pub fn write_text<T: Display + ?Sized>(&mut self, text: &T) -> Result<()> {
+        self.write_text_fmt(format_args!("{}", text))
+    }
+
+    /// Writes a text node and returns the number of bytes written.
+    ///
+    /// The number includes everything written by this call: the end of the start tag,
+    /// the new line and indention before the text and the escaped text itself.
+    /// Trailing whitespaces held back by `Options::trim_trailing_whitespace`
+    /// are counted by the call that writes them out.
+    ///
+    /// See [`write_text_fmt()`] for details.
+    ///
+    /// [`write_text_fmt()`]: struct.XmlWriter.html#method.write_text_fmt
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options { indent: Indent::None, ..Options::default() };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("p")?;
+    ///     // `>` and `a &amp; b`
+    ///     assert_eq!(w.write_text_counted("a & b")?, 10);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_text_counted<T: Display + ?Sized>(&mut self, text: &T) -> Result<usize> {
+        let start = self.fmt_writer.written;
+        self.write_text(text)?;
+        Ok(self.fmt_writer.written - start)
+    }
+
+    /// Writes a text node when the text is `Some`.
+    ///
+    /// Does nothing on `None`, so an element without other content stays self-closing.
+    ///
+    /// See [`write_text_fmt()`] for details.
+    ///
+    /// [`write_text_fmt()`]: struct.XmlWriter.html#method.write_text_fmt
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.start_element("title")?;
+    ///     w.write_text_opt(Some("Title"))?;
+    ///     w.end_element()?;
+    ///     w.start_element("desc")?;
+    ///     w.write_text_opt(None::<&str>)?;
+    ///     w.end_element()?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg>
+    ///     <title>
+    ///         Title
+    ///     </title>
+    ///     <desc/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_text_opt<T: Display + ?Sized>(&mut self, text: Option<&T>) -> Result<()> {
+        match text {
+            Some(text) => self.write_text(text),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a formatted text node.
+    ///
+    /// `<&` will be escaped. `>` is escaped according to `Options::text_gt_escaping`.
+    ///
+    /// Inside elements listed in `Options::cdata_content_elements`, the text is written
+    /// as CDATA instead, unless it contains the literal `]]>`.
+    ///
+    /// Empty text opens the element, unless `Options::skip_empty_text` is enabled.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the text contains a character not allowed by `Options::xml_version`.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When called outside the root element and `Options::strict` is enabled.
+    pub fn write_text_fmt(&mut self, fmt: fmt::Arguments) -> Result<()> {
+        let is_cdata_content = self.is_cdata_content_element();
+        if is_cdata_content || self.opt.skip_empty_text {
+            let text = fmt.to_string();
+            if text.is_empty() && self.opt.skip_empty_text {
+                return Ok(());
+            }
+
+            let cdata = is_cdata_content && !text.contains("]]>");
+            return self.write_text_fmt_impl(format_args!("{}", text), cdata);
+        }
+
+        self.write_text_fmt_impl(fmt, false)
+    }
+
+    fn is_cdata_content_element(&self) -> bool {
+        match self.depth_stack.last() {
+            Some(&DepthData {
+                element_name: Some(ref name),
+                ..
+            }) => self.opt.cdata_content_elements.contains(&name.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Appends text to the previous text node.
+    ///
+    /// When the previous node is a text node in the same element,
+    /// the text is written right after it, without a new line and indentation.
+    /// Otherwise, it's the same as [`write_text()`].
+    ///
+    /// `Options::text_wrap_width` is not applied to the appended text.
+    ///
+    /// [`write_text()`]: struct.XmlWriter.html#method.write_text
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When called outside the root element and `Options::strict` is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("p")?;
+    ///     w.write_text_append("Hello, ")?;
+    ///     w.write_text_append("World!")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<p>
+    ///     Hello, World!
+    /// </p>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_text_append<T: Display + ?Sized>(&mut self, text: &T) -> Result<()> {
+        if self.state != State::Document || self.text_end != Some(self.fmt_writer.offset) {
+            return self.write_text(text);
+        }
+
+        // Keep text_brackets, since this is still the same text node.
+        self.fmt_writer.escape = Some(Escape::Text);
+        self.fmt_writer
+            .write_fmt(format_args!("{}", text))
+            .map_err(|_| self.fmt_writer.take_err())?;
+        self.text_end = Some(self.fmt_writer.offset);
+
+        Ok(())
+    }
+
+    /// Writes a text node from a sequence of chunks.
+    ///
+    /// Chunks are written one after another, without new lines in between,
+    /// so a large text can be streamed without collecting it first.
+    /// Nothing is written when there are no chunks.
+    ///
+    /// `<&` will be escaped. `>` is escaped according to `Options::text_gt_escaping`.
+    /// `Options::text_wrap_width` is not applied.
+    ///
+    /// # Errors
+    ///
+    /// - The first error returned by `chunks`. Chunks written before it are kept.
+    /// - `InvalidData` when a chunk contains a character not allowed by `Options::xml_version`.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When called outside the root element and `Options::strict` is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options { indent: Indent::None, ..Options::default() };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("csv")?;
+    ///     let rows = (1..4).map(|i| Ok(format!("{},{}\n", i, i * i)));
+    ///     w.write_text_chunks(rows)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<csv>1,1\n2,4\n3,9\n</csv>"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_text_chunks<I>(&mut self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<String>>,
+    {
+        let mut start = None;
+        let mut res = Ok(());
+        for chunk in chunks {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    res = Err(e);
+                    break;
+                }
+            };
+
+            if start.is_none() {
+                start = Some(self.begin_text(false)?);
+                self.fmt_writer.text_brackets = 0;
+            }
+
+            self.fmt_writer.escape = Some(Escape::Text);
+            res = self
+                .fmt_writer
+                .write_fmt(format_args!("{}", chunk))
+                .map_err(|_| self.fmt_writer.take_err());
+            if res.is_err() {
+                break;
+            }
+        }
+
+        // Finish the text node even on error, to keep the writer state consistent.
+        if let Some((line, column)) = start {
+            self.end_text(false, line, column);
+        }
+
+        res
+    }
+
+    /// Writes a number as a text node using a fixed-point notation.
+    ///
+    /// Unlike `fmt::Display`, never uses a scientific notation,
+    /// which makes the output suitable for types like `xs:decimal`.
+    /// The decimal separator is always `.`.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the value is NaN or infinite, since `xs:decimal` has no such values.
+    ///   Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options { indent: Indent::None, ..Options::default() };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("price")?;
+    ///     w.write_decimal_text(12.5, 2)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<price>12.50</price>"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_decimal_text(&mut self, value: f64, decimals: usize) -> Result<()> {
+        if !value.is_finite() {
+            return Err(invalid_data_error("decimal value must be finite"));
+        }
+
+        self.write_text_fmt(format_args!("{:.*}", decimals, value))
+    }
+
+    /// Writes a multi-line text node, keeping the indention of each line.
+    ///
+    /// Each line is prefixed with the current node indention,
+    /// while its own leading whitespaces are preserved.
+    /// Empty lines are not indented.
+    ///
+    /// `<&` will be escaped. `>` is escaped according to `Options::text_gt_escaping`.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("pre")?;
+    ///     w.write_preformatted_text("if a {\n    b();\n}")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<pre>
+    ///     if a {
+    ///         b();
+    ///     }
+    /// </pre>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_preformatted_text(&mut self, text: &str) -> Result<()> {
+        let (line, column) = self.begin_text(false)?;
+        self.fmt_writer.text_brackets = 0;
+        for (i, text_line) in text.split('\n').enumerate() {
+            if i > 0 {
+                if self.opt.indent == Indent::None || self.preserve_whitespaces {
+                    // Keep the original new line, since no new lines are written otherwise.
+                    self.fmt_writer.write_raw("\n")?;
+                } else {
+                    self.write_new_line()?;
+                    if !text_line.is_empty() {
+                        self.write_node_indent()?;
+                    }
+                }
+            }
+
+            self.fmt_writer.write_escaped(text_line, false)?;
+        }
+        self.end_text(false, line, column);
+
+        Ok(())
+    }
+
+    /// Writes whitespaces as is at the current position.
+    ///
+    /// Unlike text, no new line and indention are written before,
+    /// which gives a precise control over spacing between inline elements
+    /// when whitespaces are preserved.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when `ws` contains anything but spaces, tabs and new lines.
+    ///   Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When whitespaces are not preserved, since they would be mixed with the indention.
+    ///   See `set_preserve_whitespaces()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.set_preserve_whitespaces(true);
+    ///     w.start_element("p")?;
+    ///     w.start_element("b")?;
+    ///     w.write_text("A")?;
+    ///     w.end_element()?;
+    ///     w.write_whitespace(" ")?;
+    ///     w.start_element("b")?;
+    ///     w.write_text("B")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<p><b>A</b> <b>B</b></p>"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_whitespace(&mut self, ws: &str) -> Result<()> {
+        if !ws.chars().all(is_xml_whitespace) {
+            return Err(invalid_data_error(
+                "whitespace must contain only spaces, tabs and new lines",
+            ));
+        }
+
+        if self.state == State::Empty || self.depth_stack.is_empty() {
+            panic!("must be called after start_element()");
+        }
+
+        if !self.preserve_whitespaces {
+            panic!("write_whitespace requires preserved whitespaces");
+        }
+
+        if self.state == State::Attributes {
+            self.write_open_element()?;
+        }
+
+        self.fmt_writer.write_raw(ws)
+    }
+
+    /// Writes text inside a `<![CDATA[ ... ]]>` node.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When the text contains the literal `]]>`.
+    pub fn write_cdata_text(&mut self, text: &str) -> Result<()> {
+        if text.contains("]]>") {
+            panic!("CDATA text must not contain `]]>'");
+        }
+        self.write_text_fmt_impl(format_args!("{}", text), true)
+    }
+
+    /// Writes text inside a `<![CDATA[ ... ]]>` node.
+    ///
+    /// Unlike [`write_cdata_text()`], doesn't panic when the text contains the literal `]]>`.
+    ///
+    /// [`write_cdata_text()`]: struct.XmlWriter.html#method.write_cdata_text
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when the text contains the literal `]]>`. Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    pub fn try_write_cdata_text(&mut self, text: &str) -> Result<()> {
+        if text.contains("]]>") {
+            return Err(invalid_data_error("CDATA text must not contain `]]>'"));
+        }
+        self.write_text_fmt_impl(format_args!("{}", text), true)
+    }
+
+    /// Writes an element with the base64-encoded data as its text.
+    ///
+    /// Uses the standard alphabet with padding. The encoded text is written on a single line,
+    /// unless `Options::base64_wrap_width` is set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("data")?;
+    ///     w.write_base64_element("blob", b"hello")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<data>
+    ///     <blob>
+    ///         aGVsbG8=
+    ///     </blob>
+    /// </data>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline(never)]
+    pub fn write_base64_element(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        self.start_element(name)?;
+        if !data.is_empty() {
+            let (line, column) = self.begin_text(false)?;
+
+            let wrap_width = self
+                .opt
+                .base64_wrap_width
+                .map(|width| core::cmp::max(width / 4 * 4, 4));
+
+            // Encode in chunks to avoid writing each quad separately.
+            let mut buf = [0; 64];
+            let mut len = 0;
+            let mut line_len = 0;
+            for triple in data.chunks(3) {
+                let wrap = wrap_width == Some(line_len);
+                if len == buf.len() || wrap {
+                    self.fmt_writer
+                        .write_raw(core::str::from_utf8(&buf[..len]).expect("base64 is ASCII"))?;
+                    len = 0;
+                }
+
+                if wrap {
+                    self.fmt_writer.write_raw(self.opt.line_ending.as_str())?;
+                    self.write_node_indent()?;
+                    line_len = 0;
+                }
+
+                let b0 = triple[0] as usize;
+                let b1 = triple.get(1).map_or(0, |&b| b as usize);
+                let b2 = triple.get(2).map_or(0, |&b| b as usize);
+                buf[len] = ALPHABET[b0 >> 2];
+                buf[len + 1] = ALPHABET[((b0 & 0x3) << 4) | (b1 >> 4)];
+                buf[len + 2] = if triple.len() > 1 {
+                    ALPHABET[((b1 & 0xF) << 2) | (b2 >> 6)]
+                } else {
+                    b'='
+                };
+                buf[len + 3] = if triple.len() > 2 {
+                    ALPHABET[b2 & 0x3F]
+                } else {
+                    b'='
+                };
+                len += 4;
+                line_len += 4;
+            }
+
+            self.fmt_writer
+                .write_raw(core::str::from_utf8(&buf[..len]).expect("base64 is ASCII"))?;
+
+            self.end_text(false, line, column);
+        }
+        self.end_element()
+    }
+
+    /// Writes a text node, using CDATA when the text contains markup characters.
+    ///
+    /// Text that contains any of `<>&` is written via [`write_cdata_text()`],
+    /// unless it contains the literal `]]>`. Otherwise, it's written via [`write_text()`].
+    ///
+    /// [`write_cdata_text()`]: struct.XmlWriter.html#method.write_cdata_text
+    /// [`write_text()`]: struct.XmlWriter.html#method.write_text
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("code")?;
+    ///     w.write_text_auto("a < b")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<code><![CDATA[
+    ///     a < b
+    /// ]]></code>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_text_auto(&mut self, text: &str) -> Result<()> {
+        let has_markup = text.contains(|c| c == '<' || c == '>' || c == '&');
+        if has_markup && !text.contains("]]>") {
+            self.write_cdata_text(text)
+        } else {
+            self.write_text(text)
+        }
+    }
+
+    /// Writes a text node with whitespaces collapsed like in HTML.
+    ///
+    /// Runs of spaces, tabs, new lines, form feeds and carriage returns are replaced
+    /// with a single space and removed at the start and at the end of the text.
+    ///
+    /// See [`write_text_fmt()`] for details.
+    ///
+    /// [`write_text_fmt()`]: struct.XmlWriter.html#method.write_text_fmt
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("p")?;
+    ///     w.write_html_text("  Hello,\n\n   world!  ")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<p>
+    ///     Hello, world!
+    /// </p>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_html_text(&mut self, text: &str) -> Result<()> {
+        self.write_text_fmt(format_args!("{}", CollapsedWhitespace(text)))
+    }
+
+    /// Writes a text node with numbers separated by a space, like SVG `points`.
+    ///
+    /// Each number is written with up to `precision` digits after the decimal point,
+    /// without trailing zeros.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidData` when any value is NaN or infinite. Nothing is written in this case.
+    ///
+    /// # Panics
+    ///
+    /// - When called not after `start_element()`.
+    /// - When called outside the root element and `Options::strict` is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let opt = Options { indent: Indent::None, ..Options::default() };
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("points")?;
+    ///     w.write_numeric_text(&[1.0, 2.5, 3.333333], 2)?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    ///         "<points>1 2.5 3.33</points>"
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_numeric_text(&mut self, values: &[f64], precision: usize) -> Result<()> {
+        if !values.iter().all(|value| value.is_finite()) {
+            return Err(invalid_data_error("numeric values must be finite"));
+        }
+
+        let (line, column) = self.begin_text(false)?;
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.fmt_writer.write_raw(" ")?;
+            }
+
+            self.scratch.clear();
+            self.scratch
+                .write_fmt(format_args!("{:.*}", precision, value))
+                .map_err(|_| formatter_error())?;
+
+            let mut number = self.scratch.as_str();
+            if number.contains('.') {
+                number = number.trim_end_matches('0').trim_end_matches('.');
+            }
+
+            if number == "-0" {
+                number = "0";
+            }
+
+            self.fmt_writer.write_raw(number)?;
+        }
+        self.end_text(false, line, column);
+
+        Ok(())
+    }
+
+    /// Writes formatted data as is, without any escaping and indentation.
+    ///
+    /// **Warning:** the data is not validated in any way and the writer state is not changed,
+    /// so it's up to the caller to make sure that the output is still a valid XML.
+    /// For example, writing right after `start_element()` will put the data inside the start tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.write_text("")?;
+    ///     w.write_raw_fmt(format_args!("<rect width=\"{}\"/>", 10))?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg>
+    ///     <rect width=\"10\"/>
+    /// </svg>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_raw_fmt(&mut self, fmt: fmt::Arguments) -> Result<()> {
+        self.fmt_writer.escape = Some(Escape::Raw);
+        self.fmt_writer
+            .write_fmt(fmt)
+            .map_err(|_| self.fmt_writer.take_err())
+    }
+
+    #[inline(never)]
+    fn write_text_fmt_impl(&mut self, fmt: fmt::Arguments, cdata: bool) -> Result<()> {
+        let (line, column) = self.begin_text(cdata)?;
+        match self.opt.text_wrap_width {
+            Some(width)
+                if !cdata && self.opt.indent != Indent::None && !self.preserve_whitespaces =>
+            {
+                // Wrapping requires the whole text to be known beforehand.
+                self.write_wrapped_text(&fmt.to_string(), width)?;
+            }
+            _ => {
+                self.fmt_writer.text_brackets = 0;
+                self.fmt_writer.escape = Some(if cdata { Escape::CData } else { Escape::Text });
+                self.fmt_writer
+                    .write_fmt(fmt)
+                    .map_err(|_| self.fmt_writer.take_err())?;
+            }
+        }
+        self.end_text(cdata, line, column);
+
+        Ok(())
+    }
+
+    // Writes everything that precedes the text itself.
+    // Returns the text start position.
+    fn begin_text(&mut self, cdata: bool) -> Result<(usize, usize)> {
+        if self.opt.strict && self.depth_stack.is_empty() {
+            panic!("text not allowed at document level");
+        }
+
+        if self.state == State::Empty || self.depth_stack.is_empty() {
+            panic!("must be called after start_element()");
+        }
+
+        let is_sibling = self.state == State::Document;
+        let is_first_child = self.state == State::Attributes && self.attribute_children.is_empty();
+
+        if self.state == State::Attributes {
+            self.write_open_element()?;
+        }
+
+        if cdata && self.state != State::CData {
+            if is_first_child && self.opt.newline_after_open_tag && !self.preserve_whitespaces {
+                self.write_new_line()?;
+                self.write_node_indent()?;
+            }
+
+            self.fmt_writer.write_raw("<![CDATA[")?;
+        }
+
+        let inline_text = self.opt.inline_text && !self.opt.newline_after_open_tag;
+        if is_first_child && inline_text && !self.preserve_whitespaces {
+            let offset = self.fmt_writer.offset;
+            if let Some(depth) = self.depth_stack.last_mut() {
+                depth.inline_start = Some(offset);
+            }
+        } else {
+            if self.state != State::Empty {
+                self.write_new_line()?;
+            }
+
+            if is_sibling {
+                self.write_sibling_separator()?;
+            }
+
+            self.write_node_indent()?;
+        }
+
+        Ok((self.fmt_writer.line, self.fmt_writer.column))
+    }
+
+    fn end_text(&mut self, cdata: bool, line: usize, column: usize) {
+        if self.state == State::Attributes {
+            self.depth_stack.push(DepthData {
+                element_name: None,
+                has_children: false,
+                inline_start: None,
+                nowrap: false,
+            });
+        }
+
+        if let Some(ref mut observer) = self.observer.0 {
+            observer.on_text(line, column);
+        }
+
+        self.text_end = if cdata {
+            None
+        } else {
+            Some(self.fmt_writer.offset)
+        };
+
+        self.state = if cdata { State::CData } else { State::Document };
+    }
+
+    fn write_wrapped_text(&mut self, text: &str, width: usize) -> Result<()> {
+        let indent_width = self.node_indent_width();
+        let mut column = indent_width;
+        for (i, word) in text.split_whitespace().enumerate() {
+            let len = word.chars().count();
+            if i > 0 {
+                if column + 1 + len > width {
+                    self.write_new_line()?;
+                    self.write_node_indent()?;
+                    column = indent_width;
+                } else {
+                    self.fmt_writer.write_raw(" ")?;
+                    column += 1;
+                }
+            }
+
+            self.fmt_writer.text_brackets = 0;
+            self.fmt_writer.write_escaped(word, false)?;
+            column += len;
+        }
+
+        Ok(())
+    }
+
+    // Writes the comment text on separate lines, indented one level deeper than the comment.
+    // The comment end goes on its own line too.
+    fn write_wrapped_comment(&mut self, text: &str, width: usize) -> Result<()> {
+        let depth = self.node_depth() + 1;
+        let indent_width = depth * self.indent_level_width();
+        let mut column = indent_width;
+        for (i, word) in text.split_whitespace().enumerate() {
+            let len = word.chars().count();
+            if i == 0 || column + 1 + len > width {
+                self.write_new_line()?;
+                self.write_indent(depth, self.opt.indent)?;
+                column = indent_width;
+            } else {
+                self.fmt_writer.write_raw(" ")?;
+                column += 1;
+            }
+
+            // Words are separated, so `--` cannot be formed between them.
+            self.fmt_writer.comment_last = Some(' ');
+            self.fmt_writer.write_comment_escaped(word)?;
+            column += len;
+        }
+
+        self.write_new_line()?;
+        self.write_node_indent()?;
+        self.fmt_writer.comment_last = None;
+        Ok(())
+    }
+
+    /// Closes an open element.
+    ///
+    /// Does nothing when there are no open elements.
+    ///
+    /// # Panics
+    ///
+    /// - When called before anything was written and `Options::strict` is enabled.
+    #[inline(never)]
+    pub fn end_element(&mut self) -> Result<()> {
+        if self.opt.strict && self.state == State::Empty {
+            panic!("end_element called before any element was started");
+        }
+
+        self.check_attribute_closed();
+
+        if self.state == State::Attributes && !self.attribute_children.is_empty() {
+            self.write_open_element()?;
+        }
+
+        self.flush_attributes()?;
+
+        if let Some(depth) = self.depth_stack.pop() {
+            let line;
+            let column;
+            let self_closing = self.opt.enable_self_closing
+                || depth.element_name.as_ref().map_or(false, |name| {
+                    self.opt.always_self_close.contains(&name.as_str())
+                });
+
+            if depth.has_children || !self_closing {
+                // Close the empty node here as there were no children to close it.
+                if !depth.has_children {
+                    self.fmt_writer.write_raw(">")?;
+                }
+
+                // Nothing was written on a new line after the inline text.
+                let is_inline = depth
+                    .inline_start
+                    .map_or(false, |start| start >= self.new_line_end);
+
+                if !self.preserve_whitespaces && !is_inline {
+                    self.write_new_line()?;
+                    self.write_node_indent()?;
+                }
+
+                if self.state == State::CData {
+                    self.fmt_writer.write_raw("]]>")?;
+                }
+
+                line = self.fmt_writer.line;
+                column = self.fmt_writer.column;
+                self.fmt_writer.write_raw("</")?;
+
+                // Write the previous opening element name as closing element now.
+                self.fmt_writer.write_raw(
+                    depth
+                        .element_name
+                        .as_ref()
+                        .expect("did not have opening element name when closing element"),
+                )?;
+
+                self.fmt_writer.write_raw(">")?;
+            } else {
+                if self.opt.space_before_self_close && !self.opt.self_close_marker.starts_with(' ')
+                {
+                    self.fmt_writer.write_raw(" ")?;
+                }
+
+                line = self.fmt_writer.line;
+                column = self.fmt_writer.column;
+                self.fmt_writer.write_raw(self.opt.self_close_marker)?;
+            }
+
+            if let (Some(observer), Some(name)) = (self.observer.0.as_mut(), &depth.element_name) {
+                observer.on_end_element(name, line, column);
+            }
+        }
+
+        self.state = State::Document;
+
+        Ok(())
+    }
+
+    /// Starts writing a new element and returns its token.
+    ///
+    /// Same as `start_element()`, but the element must be closed
+    /// via `end_element_token()`.
+    ///
+    /// # Example
     ///
     /// ```
     /// use xmlwriter::*;
     /// use std::io;
     ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
     /// fn main() -> io::Result<()> {
     ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    ///     w.start_element("svg")?;
-    ///     w.write_attribute("x", "5")?;
-    ///     w.write_attribute("y", &5)?;
+    ///     let svg = w.start_element_token("svg")?;
+    ///     let rect = w.start_element_token("rect")?;
+    ///     w.end_element_token(rect)?;
+    ///     w.end_element_token(svg)?;
     ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
     ///         .expect("xmlwriter should always produce valid UTF-8"),
-    ///         "<svg x=\"5\" y=\"5\"/>\n",
+    /// "<svg>
+    ///     <rect/>
+    /// </svg>
+    /// "
     ///     );
     ///     Ok(())
     /// }
     /// ```
-    pub fn write_attribute<V: Display + ?Sized>(
-        &mut self,
-        name: &str,
-        value: &V,
-    ) -> io::Result<()> {
-        self.write_attribute_fmt(name, format_args!("{}", value))
+    pub fn start_element_token(&mut self, name: &str) -> Result<ElementToken> {
+        self.start_element(name)?;
+        Ok(ElementToken {
+            depth: self.depth_stack.len(),
+        })
     }
 
-    /// Writes a formatted attribute value.
-    ///
-    /// Any occurrence of `&<>"'` in the value will be escaped.
+    /// Closes an element opened by `start_element_token()`.
     ///
     /// # Panics
     ///
-    /// - When called before `start_element()`.
-    /// - When called after `close_element()`.
+    /// - When the token doesn't belong to the current element.
+    pub fn end_element_token(&mut self, token: ElementToken) -> Result<()> {
+        if token.depth != self.depth_stack.len() {
+            panic!("element token doesn't match the current element");
+        }
+
+        self.end_element()
+    }
+
+    /// Closes the specified amount of open elements.
+    ///
+    /// Stops when there are no more open elements.
+    pub fn end_elements(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            if self.depth_stack.is_empty() {
+                break;
+            }
+
+            self.end_element()?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes open elements until only `depth` elements are left open.
+    ///
+    /// Does nothing when the current depth is already less or equal to `depth`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("html")?;
+    ///     let body = w.depth();
+    ///     w.start_element("div")?;
+    ///     w.start_element("p")?;
+    ///     w.close_to_depth(body)?;
+    ///     w.start_element("footer")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<html>
+    ///     <div>
+    ///         <p/>
+    ///     </div>
+    ///     <footer/>
+    /// </html>
+    /// "
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn close_to_depth(&mut self, depth: usize) -> Result<()> {
+        while self.depth_stack.len() > depth {
+            self.end_element()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of open elements.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth_stack.len()
+    }
+
+    /// Closes all open elements and returns back the writer.
     ///
     /// # Example
     ///
@@ -454,101 +5758,428 @@ impl<'a, W: Write> XmlWriter<'a, W> {
     /// use xmlwriter::*;
     /// use std::io;
     ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
     /// fn main() -> io::Result<()> {
     ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.start_element("g")?;
     ///     w.start_element("rect")?;
-    ///     w.write_attribute_fmt("fill", format_args!("url(#{})", "gradient"))?;
     ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
     ///         .expect("xmlwriter should always produce valid UTF-8"),
-    ///         "<rect fill=\"url(#gradient)\"/>\n"
+    /// "<svg>
+    ///     <g>
+    ///         <rect/>
+    ///     </g>
+    /// </svg>
+    /// "
     ///     );
     ///     Ok(())
     /// }
     /// ```
+    pub fn end_document(self) -> Result<W> {
+        self.into_parts().map(|(writer, _)| writer)
+    }
+
+    /// Closes all open elements and returns back the writer
+    /// and the number of elements that were closed.
+    ///
+    /// A non-zero number means that some elements were not closed explicitly.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xmlwriter::*;
+    /// use std::io;
+    ///
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// # #[cfg(feature = "std")]
+    /// fn main() -> io::Result<()> {
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    ///     w.start_element("svg")?;
+    ///     w.start_element("rect")?;
+    ///     w.end_element()?;
+    ///     let (_, closed) = w.end_document_with_stats()?;
+    ///     assert_eq!(closed, 1);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn end_document_with_stats(self) -> Result<(W, usize)> {
+        let closed = self
+            .depth_stack
+            .iter()
+            .filter(|depth| depth.element_name.is_some())
+            .count();
+        self.end_document().map(|writer| (writer, closed))
+    }
+
+    /// Closes all open elements and returns back the writer and the stack,
+    /// which can be passed to [`from_parts()`] to write another document.
+    ///
+    /// [`from_parts()`]: struct.XmlWriter.html#method.from_parts
+    pub fn into_parts(mut self) -> Result<(W, DepthStack)> {
+        self.finish()?;
+        Ok((self.fmt_writer.writer, DepthStack(self.depth_stack)))
+    }
+
+    // Closes all open elements and writes out everything held back.
+    fn finish(&mut self) -> Result<()> {
+        while !self.depth_stack.is_empty() {
+            self.end_element()?;
+        }
+
+        self.write_new_line()?;
+        self.fmt_writer.flush_pending_whitespace()?;
+
+        if let Some((start, end)) = self.fmt_writer.encoding_range.take() {
+            self.fmt_writer.buffering -= 1;
+
+            let mut buffer = core::mem::replace(&mut self.fmt_writer.buffer, String::new());
+            if buffer.is_ascii() {
+                buffer.replace_range(start..end, "");
+
+                let removed = end - start;
+                self.fmt_writer.offset -= removed;
+                for node in &mut self.node_offsets {
+                    node.0 -= removed;
+                }
+            }
+
+            self.fmt_writer.write_to_writer(&buffer)?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn get_quote_char(&self) -> &'static str {
+        if self.opt.use_single_quote {
+            "'"
+        } else {
+            "\""
+        }
+    }
+
+    // Writes quote unescaped, so only use when appropriate.
+    #[inline]
+    fn write_quote(&mut self) -> Result<()> {
+        self.fmt_writer.write_raw(self.get_quote_char())
+    }
+
+    // Writes the end of the current opening element, so `>`.
+    fn write_open_element(&mut self) -> Result<()> {
+        self.check_attribute_closed();
+        self.flush_attributes()?;
+
+        if let Some(depth) = self.depth_stack.last_mut() {
+            depth.has_children = true;
+            self.fmt_writer.write_raw(">")?;
+
+            self.state = State::Document;
+        }
+
+        if !self.attribute_children.is_empty() {
+            self.write_attribute_children()?;
+        }
+
+        Ok(())
+    }
+
+    // Writes captured attributes in the schema order
+    // or with namespace declarations first.
+    fn flush_attributes(&mut self) -> Result<()> {
+        if !self.fmt_writer.capturing {
+            return Ok(());
+        }
+
+        self.fmt_writer.capturing = false;
+        let captured = core::mem::replace(&mut self.fmt_writer.captured, String::new());
+
+        let mut end = captured.len();
+        for range in self.attribute_ranges.iter_mut().rev() {
+            range.2 = end;
+            end = range.1;
+        }
+        if self.opt.namespace_decls_first {
+            self.attribute_ranges.sort_by_key(|range| {
+                let name = captured_attribute_name(&captured[range.1..range.2]);
+                (!is_namespace_decl(name), name)
+            });
+        } else {
+            // Stable, so attributes with the same rank keep their order.
+            self.attribute_ranges.sort_by_key(|range| range.0);
+        }
+
+        // Aligned names are padded up to the longest one.
+        let nowrap = self.depth_stack.last().map_or(false, |depth| depth.nowrap);
+        let name_width = if self.is_aligning_attributes() && !nowrap {
+            self.attribute_ranges
+                .iter()
+                .map(|range| {
+                    captured_attribute_name(&captured[range.1..range.2])
+                        .chars()
+                        .count()
+                })
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+
+        // Anything written before the first attribute.
+        let mut result = self.fmt_writer.write_direct(&captured[..end]);
+        let mut padding = 0;
+        for &(_, start, end) in &self.attribute_ranges {
+            let attribute = &captured[start..end];
+            let name = captured_attribute_name(attribute);
+            let name_end = attribute.len() - attribute.trim_start().len() + name.len();
+
+            // Attributes without a value, like minimized ones, are not padded.
+            padding = if attribute[name_end..].starts_with('=') {
+                name_width.saturating_sub(name.chars().count())
+            } else {
+                0
+            };
+
+            if result.is_ok() {
+                result = self.fmt_writer.write_direct(&attribute[..name_end]);
+            }
+            while self.padding.spaces.len() < padding {
+                self.padding.spaces.push(' ');
+            }
+            if result.is_ok() {
+                result = self
+                    .fmt_writer
+                    .write_direct(&self.padding.spaces[..padding]);
+            }
+            if result.is_ok() {
+                result = self.fmt_writer.write_direct(&attribute[name_end..]);
+            }
+            self.fmt_writer.offset += padding;
+        }
+        // Each attribute is on its own line, so only the last one affects the column.
+        self.fmt_writer.column += padding;
+
+        self.attribute_ranges.clear();
+        // Keep the buffer capacity.
+        self.fmt_writer.captured = captured;
+        self.fmt_writer.captured.clear();
+
+        result
+    }
+
+    // Writes attributes deferred by Options::attribute_to_element_if as child elements.
     #[inline(never)]
-    pub fn write_attribute_fmt(&mut self, name: &str, fmt: fmt::Arguments) -> io::Result<()> {
-        if self.state != State::Attributes {
+    fn write_attribute_children(&mut self) -> Result<()> {
+        let children = core::mem::replace(&mut self.attribute_children, Vec::new());
+        for (name, value) in &children {
+            self.start_element(name)?;
+            self.write_text(value)?;
+            self.end_element()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_node_indent(&mut self) -> Result<()> {
+        if self.is_recording_offsets() {
+            let (offset, depth) = (self.fmt_writer.offset, self.node_depth());
+            if let Some(last) = self.node_offsets.last_mut() {
+                if last.0 == offset {
+                    last.1 = depth;
+                }
+            }
+        }
+
+        self.write_indent(self.node_depth(), self.opt.indent)
+    }
+
+    fn is_recording_offsets(&self) -> bool {
+        self.opt.record_node_offsets
+            && self.opt.indent == Indent::None
+            && !self.preserve_whitespaces
+    }
+
+    fn is_aligning_attributes(&self) -> bool {
+        self.opt.align_attribute_names && self.opt.attributes_indent != Indent::None
+    }
+
+    fn check_attributes_allowed(&self) {
+        self.check_attribute_closed();
+
+        if self.state == State::Attributes {
+            return;
+        }
+
+        if self.depth_stack.is_empty() {
             panic!("must be called after start_element()");
+        } else {
+            panic!("cannot write attributes after children have been added to this element");
         }
+    }
 
-        self.write_attribute_prefix(name)?;
-        self.fmt_writer.escape = Some(Escape::AttributeValue);
-        self.fmt_writer
-            .write_fmt(fmt)
-            .map_err(|_| self.fmt_writer.take_err())?;
-        self.write_quote()
+    fn check_attribute_closed(&self) {
+        if self.attribute_open {
+            panic!("attribute is not closed, call end_attribute() first");
+        }
     }
 
-    /// Writes a raw attribute value, without performing escaping.
+    // Returns the current node depth, including the base depth.
+    fn node_depth(&self) -> usize {
+        self.base_depth + self.depth_stack.len()
+    }
+
+    // Returns the width of the current node indention, in columns.
+    fn node_indent_width(&self) -> usize {
+        self.node_depth() * self.indent_level_width()
+    }
+
+    // Returns the width of a single indention level, in columns.
+    fn indent_level_width(&self) -> usize {
+        match self.opt.indent {
+            Indent::Tabs => self.opt.tab_width as usize,
+            indent => indent.width(),
+        }
+    }
+
+    fn write_indent(&mut self, depth: usize, indent: Indent) -> Result<()> {
+        if self.preserve_whitespaces {
+            return Ok(());
+        }
+
+        let (padding, c, len) = match indent {
+            Indent::None => return Ok(()),
+            Indent::Spaces(n) => (&mut self.padding.spaces, ' ', depth * n as usize),
+            Indent::Tabs => (&mut self.padding.tabs, '\t', depth),
+        };
+
+        // Write the whole indent at once, growing the padding on demand.
+        while padding.len() < len {
+            padding.push(c);
+        }
+
+        self.fmt_writer.write_raw(&padding[..len])
+    }
+
+    // Separates sibling nodes with a space in a compact mode.
+    fn write_sibling_separator(&mut self) -> Result<()> {
+        if self.opt.indent == Indent::None
+            && self.opt.space_between_siblings_when_compact
+            && !self.preserve_whitespaces
+        {
+            self.fmt_writer.write_raw(" ")?;
+        }
+        Ok(())
+    }
+
+    fn write_new_line(&mut self) -> Result<()> {
+        if self.opt.indent != Indent::None && !self.preserve_whitespaces {
+            self.fmt_writer.write_raw(self.opt.line_ending.as_str())?;
+            self.new_line_end = self.fmt_writer.offset;
+        } else if self.is_recording_offsets() {
+            // The depth is set by the following write_node_indent(), if any.
+            self.node_offsets.push((self.fmt_writer.offset, 0));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl XmlWriter<Vec<u8>> {
+    /// Removes the current element, as if `start_element()` was never called.
     ///
-    /// Closure provides a mutable reference to the writer.
+    /// Allows deciding that an element shouldn't exist after it was started.
+    /// Everything written by `start_element()` is removed from the buffer,
+    /// including the preceding new line and indention.
     ///
-    /// **Warning:** this method is an escape hatch for cases when you need to write
-    /// a lot of data very fast, and as such does no validity checks whatsoever on the
-    /// written value.
+    /// `XmlEvents::on_start_element()` was already called for this element
+    /// and will not be followed by `XmlEvents::on_end_element()`.
     ///
     /// # Panics
     ///
-    /// - When called before `start_element()`.
-    /// - When called after `close_element()`.
+    /// - When the element has attributes or children.
+    /// - When the element was started before `swap_writer()`.
+    /// - When the element was started after trailing whitespaces
+    ///   and `Options::trim_trailing_whitespace` is enabled.
     ///
     /// # Example
     ///
     /// ```
     /// use xmlwriter::*;
-    /// use std::io::{self, Write};
+    /// use std::io;
     ///
     /// fn main() -> io::Result<()> {
     ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    ///     w.start_element("path")?;
-    ///     w.write_attribute_raw("d", |writer| writer.write_all(b"M 10 20 L 30 40"))?;
+    ///     w.start_element("svg")?;
+    ///     w.start_element("g")?;
+    ///     w.cancel_element()?;
     ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
     ///         .expect("xmlwriter should always produce valid UTF-8"),
-    ///         "<path d=\"M 10 20 L 30 40\"/>\n"
+    ///         "<svg/>\n"
     ///     );
     ///     Ok(())
     /// }
     /// ```
-    #[inline(never)]
-    pub fn write_attribute_raw<F>(&mut self, name: &str, f: F) -> io::Result<()>
-    where
-        F: FnOnce(&mut W) -> io::Result<()>,
-    {
-        if self.state != State::Attributes {
-            panic!("must be called after start_element()");
-        }
+    pub fn cancel_element(&mut self) -> Result<()> {
+        let start = match self.element_start {
+            Some(start)
+                if self.state == State::Attributes
+                    && self.fmt_writer.offset == start.name_end
+                    && self.attribute_children.is_empty()
+                    && !self.attribute_open =>
+            {
+                start
+            }
+            _ => panic!("the current element cannot be canceled"),
+        };
 
-        self.write_attribute_prefix(name)?;
-        f(&mut self.fmt_writer.writer)?;
-        self.write_quote()
-    }
+        self.element_start = None;
+        self.depth_stack.pop();
 
-    #[inline(never)]
-    fn write_attribute_prefix(&mut self, name: &str) -> io::Result<()> {
-        if self.opt.attributes_indent == Indent::None {
-            self.fmt_writer.writer.write_all(b" ")?;
+        let len = self.fmt_writer.offset - start.offset;
+        if self.fmt_writer.buffering != 0 {
+            let buffer = &mut self.fmt_writer.buffer;
+            buffer.truncate(buffer.len() - len);
         } else {
-            self.fmt_writer.writer.write_all(b"\n")?;
+            let writer = &mut self.fmt_writer.writer;
+            writer.truncate(writer.len() - len);
+        }
 
-            let depth = self.depth_stack.len();
-            if depth > 0 {
-                self.write_indent(depth - 1, self.opt.indent)?;
+        if start.parent_opened {
+            if let Some(depth) = self.depth_stack.last_mut() {
+                depth.has_children = false;
             }
-
-            self.write_indent(1, self.opt.attributes_indent)?;
         }
 
-        self.fmt_writer.writer.write_all(name.as_bytes())?;
-        self.fmt_writer.writer.write_all(b"=")?;
-        self.write_quote()
+        self.fmt_writer.capturing = false;
+        self.fmt_writer.line = start.line;
+        self.fmt_writer.column = start.column;
+        self.fmt_writer.offset = start.offset;
+        self.fmt_writer.text_brackets = start.text_brackets;
+        self.state = start.state;
+        self.root_written = start.root_written;
+        self.text_end = start.text_end;
+        self.new_line_end = start.new_line_end;
+        self.node_offsets.truncate(start.node_offsets_len);
+
+        Ok(())
     }
 
-    /// Sets the preserve whitespaces flag.
+    /// Closes all open elements and re-indents the written document.
     ///
-    /// - If set, text nodes will be written as is.
-    /// - If not set, text nodes will be indented.
+    /// Allows building a document in a compact mode and getting the indented one at the end.
+    /// The indentation is inserted at the node offsets recorded during writing,
+    /// so the output is the same as if `indent` was set from the start.
     ///
-    /// Can be set at any moment.
+    /// Only the part of the buffer written by this writer is affected.
+    /// After [`swap_writer()`], only the part written since the last swap.
+    ///
+    /// [`swap_writer()`]: struct.XmlWriter.html#method.swap_writer
+    ///
+    /// # Panics
+    ///
+    /// - When `Options::record_node_offsets` is not set.
+    /// - When `Options::indent` is not `Indent::None`.
     ///
     /// # Example
     ///
@@ -557,231 +6188,300 @@ impl<'a, W: Write> XmlWriter<'a, W> {
     /// use std::io;
     ///
     /// fn main() -> io::Result<()> {
-    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    ///     w.start_element("html")?;
-    ///     w.start_element("p")?;
-    ///     w.write_text("text")?;
-    ///     w.end_element()?;
-    ///     w.start_element("p")?;
-    ///     w.set_preserve_whitespaces(true);
-    ///     w.write_text("text")?;
-    ///     w.end_element()?;
-    ///     w.set_preserve_whitespaces(false);
-    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
-    ///         .expect("xmlwriter should produce valid UTF-8"),
-    /// "<html>
-    ///     <p>
-    ///         text
-    ///     </p>
-    ///     <p>text</p>
-    /// </html>
+    ///     let opt = Options {
+    ///         indent: Indent::None,
+    ///         record_node_offsets: true,
+    ///         ..Options::default()
+    ///     };
+    ///
+    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    ///     w.start_element("svg")?;
+    ///     w.start_element("rect")?;
+    ///     assert_eq!(std::str::from_utf8(w.end_document_pretty(Indent::Spaces(2))?.as_slice())
+    ///         .expect("xmlwriter should always produce valid UTF-8"),
+    /// "<svg>
+    ///   <rect/>
+    /// </svg>
     /// "
     ///     );
     ///     Ok(())
     /// }
     /// ```
-    pub fn set_preserve_whitespaces(&mut self, preserve: bool) {
-        self.preserve_whitespaces = preserve;
-    }
-
-    /// Writes a text node.
-    ///
-    /// See [`write_text_fmt()`] for details.
-    ///
-    /// [`write_text_fmt()`]: struct.XmlWriter.html#method.write_text_fmt
-    pub fn write_text<T: Display + ?Sized>(&mut self, text: &T) -> io::Result<()> {
-        self.write_text_fmt(format_args!("{}", text))
-    }
+    pub fn end_document_pretty(mut self, indent: Indent) -> Result<Vec<u8>> {
+        assert!(
+            self.opt.record_node_offsets,
+            "end_document_pretty requires Options::record_node_offsets"
+        );
+        assert!(
+            self.opt.indent == Indent::None,
+            "end_document_pretty requires a compact mode"
+        );
 
-    /// Writes a formatted text node.
-    ///
-    /// `><&` will be escaped.
-    ///
-    /// # Panics
-    ///
-    /// - When called not after `start_element()`.
-    pub fn write_text_fmt(&mut self, fmt: fmt::Arguments) -> io::Result<()> {
-        self.write_text_fmt_impl(fmt, false)
-    }
+        self.finish()?;
 
-    /// Writes text inside a `<![CDATA[ ... ]]>` node.
-    ///
-    /// # Panics
-    ///
-    /// - When called not after `start_element()`.
-    /// - When the text contains the literal `]]>`.
-    pub fn write_cdata_text(&mut self, text: &str) -> io::Result<()> {
-        if text.contains("]]>") {
-            panic!("CDATA text must not contain `]]>'");
+        let buf = self.fmt_writer.writer;
+        if indent == Indent::None {
+            return Ok(buf);
         }
-        self.write_text_fmt_impl(format_args!("{}", text), true)
-    }
 
-    #[inline(never)]
-    fn write_text_fmt_impl(&mut self, fmt: fmt::Arguments, cdata: bool) -> io::Result<()> {
-        if self.state == State::Empty || self.depth_stack.is_empty() {
-            panic!("must be called after start_element()");
-        }
+        // Node offsets are counted from the start of the first writer.
+        let start = buf.len() - (self.fmt_writer.offset - self.writer_start);
+        let mut out = Vec::with_capacity(buf.len() + self.node_offsets.len() * 5);
+        out.extend_from_slice(&buf[..start]);
 
-        if self.state == State::Attributes {
-            self.write_open_element()?;
-        }
+        let mut prev = start;
+        for &(offset, depth) in &self.node_offsets {
+            let offset = offset - self.writer_start;
+            out.extend_from_slice(&buf[prev..start + offset]);
+            out.extend_from_slice(self.opt.line_ending.as_str().as_bytes());
 
-        if cdata && self.state != State::CData {
-            self.fmt_writer.writer.write_all(b"<![CDATA[")?;
-        }
+            let (c, len) = match indent {
+                Indent::None => unreachable!(),
+                Indent::Spaces(n) => (b' ', depth * n as usize),
+                Indent::Tabs => (b'\t', depth),
+            };
+            out.extend(core::iter::repeat(c).take(len));
 
-        if self.state != State::Empty {
-            self.write_new_line()?;
+            prev = start + offset;
         }
+        out.extend_from_slice(&buf[prev..]);
 
-        self.write_node_indent()?;
-
-        self.fmt_writer.escape = Some(if cdata { Escape::CData } else { Escape::Text });
-        self.fmt_writer
-            .write_fmt(fmt)
-            .map_err(|_| self.fmt_writer.take_err())?;
+        Ok(out)
+    }
+}
 
-        if self.state == State::Attributes {
-            self.depth_stack.push(DepthData {
-                element_name: None,
-                has_children: false,
-            });
-        }
+/// Escapes an attribute value the same way `XmlWriter::write_attribute()` does.
+///
+/// `&<>` and the quote used around the value are escaped.
+/// Borrows the value when nothing has to be escaped.
+///
+/// Unlike `XmlWriter`, doesn't check for characters not allowed in XML.
+///
+/// # Example
+///
+/// ```
+/// use xmlwriter::escape_attribute_value;
+///
+/// assert_eq!(escape_attribute_value("a < 'b'", false), "a &lt; 'b'");
+/// assert_eq!(escape_attribute_value("a < 'b'", true), "a &lt; &apos;b&apos;");
+/// ```
+pub fn escape_attribute_value(value: &str, use_single_quote: bool) -> Cow<'_, str> {
+    escape_str(value, |c| {
+        escape_char(c, true, true, true, use_single_quote)
+    })
+}
 
-        self.state = if cdata { State::CData } else { State::Document };
+/// Escapes a text the same way `XmlWriter::write_text()` does with default options.
+///
+/// `&<>` are escaped.
+/// Borrows the text when nothing has to be escaped.
+///
+/// Unlike `XmlWriter`, doesn't check for characters not allowed in XML.
+///
+/// # Example
+///
+/// ```
+/// use xmlwriter::escape_text;
+///
+/// assert_eq!(escape_text("a < \"b\""), "a &lt; \"b\"");
+/// ```
+pub fn escape_text(value: &str) -> Cow<'_, str> {
+    escape_str(value, |c| escape_char(c, true, true, false, false))
+}
 
-        Ok(())
+fn escape_str<F>(value: &str, f: F) -> Cow<'_, str>
+where
+    F: Fn(char) -> Option<&'static str>,
+{
+    let mut escaped = String::new();
+    let mut part_start_pos = 0;
+    for (pos, c) in value.char_indices() {
+        if let Some(escaped_char) = f(c) {
+            escaped.push_str(&value[part_start_pos..pos]);
+            escaped.push_str(escaped_char);
+            part_start_pos = pos + c.len_utf8();
+        }
     }
 
-    /// Closes an open element.
-    #[inline(never)]
-    pub fn end_element(&mut self) -> io::Result<()> {
-        if let Some(depth) = self.depth_stack.pop() {
-            if depth.has_children || !self.opt.enable_self_closing {
-                // Close the empty node here as there were no children to close it.
-                if !depth.has_children && !self.opt.enable_self_closing {
-                    self.fmt_writer.writer.write_all(b">")?;
-                }
-
-                if !self.preserve_whitespaces {
-                    self.write_new_line()?;
-                    self.write_node_indent()?;
-                }
+    if part_start_pos == 0 {
+        return Cow::Borrowed(value);
+    }
 
-                if self.state == State::CData {
-                    self.fmt_writer.writer.write_all(b"]]>")?;
-                }
+    escaped.push_str(&value[part_start_pos..]);
+    Cow::Owned(escaped)
+}
 
-                self.fmt_writer.writer.write_all(b"</")?;
+// Returns the built-in escape for a character, if any.
+fn escape_char(
+    c: char,
+    escape_lt: bool,
+    escape_gt: bool,
+    escape_quotes: bool,
+    use_single_quote: bool,
+) -> Option<&'static str> {
+    match c {
+        '&' => Some("&amp;"),
+        '>' if escape_gt => Some("&gt;"),
+        '<' if escape_lt => Some("&lt;"),
+        '"' if escape_quotes && !use_single_quote => Some("&quot;"),
+        '\'' if escape_quotes && use_single_quote => Some("&apos;"),
+        _ => None,
+    }
+}
 
-                // Write the previous opening element name as closing element now.
-                self.fmt_writer.writer.write_all(
-                    depth
-                        .element_name
-                        .expect("did not have opening element name when closing element")
-                        .as_bytes(),
-                )?;
+// Displays a string with HTML whitespace runs collapsed into a single space
+// and without leading and trailing whitespace.
+struct CollapsedWhitespace<'a>(&'a str);
 
-                self.fmt_writer.writer.write_all(b">")?;
-            } else {
-                self.fmt_writer.writer.write_all(b"/>")?;
+impl<'a> Display for CollapsedWhitespace<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = self
+            .0
+            .split(is_html_whitespace)
+            .filter(|word| !word.is_empty());
+        for (i, word) in words.enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
             }
-        }
 
-        self.state = State::Document;
+            f.write_str(word)?;
+        }
 
         Ok(())
     }
+}
 
-    /// Closes all open elements and returns back the writer.
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use xmlwriter::*;
-    /// use std::io;
-    ///
-    /// fn main() -> io::Result<()> {
-    ///     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    ///     w.start_element("svg")?;
-    ///     w.start_element("g")?;
-    ///     w.start_element("rect")?;
-    ///     assert_eq!(std::str::from_utf8(w.end_document()?.as_slice())
-    ///         .expect("xmlwriter should always produce valid UTF-8"),
-    /// "<svg>
-    ///     <g>
-    ///         <rect/>
-    ///     </g>
-    /// </svg>
-    /// "
-    ///     );
-    ///     Ok(())
-    /// }
-    /// ```
-    pub fn end_document(mut self) -> io::Result<W> {
-        while !self.depth_stack.is_empty() {
-            self.end_element()?;
+// Advances a 1-based line and column past the given UTF-8 bytes.
+fn count_position(bytes: &[u8], line: &mut usize, column: &mut usize) {
+    for &byte in bytes {
+        if byte == b'\n' {
+            *line += 1;
+            *column = 1;
+        } else if byte & 0xC0 != 0x80 {
+            // Not a UTF-8 continuation byte, so a new character.
+            *column += 1;
         }
+    }
+}
 
-        self.write_new_line()?;
-
-        Ok(self.fmt_writer.writer)
+fn is_xml_whitespace(c: char) -> bool {
+    match c {
+        ' ' | '\t' | '\n' | '\r' => true,
+        _ => false,
     }
+}
 
-    #[inline]
-    fn get_quote_char(&self) -> u8 {
-        if self.opt.use_single_quote {
-            b'\''
-        } else {
-            b'"'
-        }
+fn is_html_whitespace(c: char) -> bool {
+    match c {
+        ' ' | '\t' | '\n' | '\u{C}' | '\r' => true,
+        _ => false,
     }
+}
 
-    // Writes quote unescaped, so only use when appropriate.
-    #[inline]
-    fn write_quote(&mut self) -> io::Result<()> {
-        self.fmt_writer.writer.write_all(&[self.get_quote_char()])
+// Returns the name of an attribute written by write_attribute_prefix().
+fn captured_attribute_name(attribute: &str) -> &str {
+    let attribute = attribute.trim_start();
+    match attribute.find('=') {
+        Some(idx) => &attribute[..idx],
+        None => attribute,
     }
+}
 
-    // Writes the end of the current opening element, so `>`.
-    fn write_open_element(&mut self) -> io::Result<()> {
-        if let Some(depth) = self.depth_stack.last_mut() {
-            depth.has_children = true;
-            self.fmt_writer.writer.write_all(b">")?;
+fn is_namespace_decl(name: &str) -> bool {
+    name == "xmlns" || name.starts_with("xmlns:")
+}
 
-            self.state = State::Document;
-        }
-        Ok(())
+// EncName ::= [A-Za-z] ([A-Za-z0-9._] | '-')*
+fn is_valid_encoding_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    match bytes.next() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        _ => return false,
     }
 
-    fn write_node_indent(&mut self) -> io::Result<()> {
-        self.write_indent(self.depth_stack.len(), self.opt.indent)
+    bytes.all(|c| c.is_ascii_alphanumeric() || c == b'.' || c == b'_' || c == b'-')
+}
+
+// Name ::= NameStartChar (NameChar)*
+//
+// Non-ASCII characters are accepted as is, like most parsers do.
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' || !c.is_ascii() => {}
+        _ => return false,
     }
 
-    fn write_indent(&mut self, depth: usize, indent: Indent) -> io::Result<()> {
-        if indent == Indent::None || self.preserve_whitespaces {
-            return Ok(());
-        }
+    chars.all(|c| {
+        c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '-' || c == '.' || !c.is_ascii()
+    })
+}
 
-        for _ in 0..depth {
-            match indent {
-                Indent::None => {}
-                Indent::Spaces(n) => {
-                    for _ in 0..n {
-                        self.fmt_writer.writer.write_all(b" ")?;
-                    }
+// A basic BCP 47 shape: `1*8ALPHA *("-" 1*8alphanum)`.
+fn is_language_tag(tag: &str) -> bool {
+    if tag.is_empty() {
+        return true;
+    }
+
+    tag.split('-').enumerate().all(|(i, subtag)| {
+        !subtag.is_empty()
+            && subtag.len() <= 8
+            && subtag.bytes().all(|b| {
+                if i == 0 {
+                    b.is_ascii_alphabetic()
+                } else {
+                    b.is_ascii_alphanumeric()
                 }
-                Indent::Tabs => self.fmt_writer.writer.write_all(b"\t")?,
-            }
-        }
-        Ok(())
+            })
+    })
+}
+
+// Checks that a character can be used in a URI as is.
+// That is, it's an unreserved or a reserved character, or a `%` of a percent-encoded octet.
+fn is_uri_char(c: char) -> bool {
+    match c {
+        'A'..='Z' | 'a'..='z' | '0'..='9' => true,
+        '-' | '.' | '_' | '~' => true,
+        ':' | '/' | '?' | '#' | '[' | ']' | '@' => true,
+        '!' | '$' | '&' | '\'' | '(' | ')' | '*' | '+' | ',' | ';' | '=' => true,
+        '%' => true,
+        _ => false,
     }
+}
 
-    fn write_new_line(&mut self) -> io::Result<()> {
-        if self.opt.indent != Indent::None && !self.preserve_whitespaces {
-            self.fmt_writer.writer.write_all(b"\n")?;
-        }
-        Ok(())
+// Checks that a character can be used in a doctype public id.
+// PubidChar ::= #x20 | #xD | #xA | [a-zA-Z0-9] | [-'()+,./:=?;!*#@$_%]
+fn is_pubid_char(c: char) -> bool {
+    match c {
+        ' ' | '\r' | '\n' => true,
+        'A'..='Z' | 'a'..='z' | '0'..='9' => true,
+        '-' | '\'' | '(' | ')' | '+' | ',' | '.' | '/' | ':' => true,
+        '=' | '?' | ';' | '!' | '*' | '#' | '@' | '$' | '_' | '%' => true,
+        _ => false,
     }
 }
+
+// Converts days since 1970-01-01 into a proleptic Gregorian (year, month, day).
+//
+// Based on http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+#[cfg(feature = "std")]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn prefixed_name(prefix: &str, local: &str) -> String {
+    let mut name = String::with_capacity(prefix.len() + 1 + local.len());
+    name.push_str(prefix);
+    name.push(':');
+    name.push_str(local);
+    name
+}
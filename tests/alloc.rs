@@ -0,0 +1,56 @@
+#![cfg(feature = "std")]
+
+// A separate test binary, since a global allocator affects all tests.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use xmlwriter::{Options, XmlWriter};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Only allocations made by the test thread are counted,
+    // since the test harness may allocate concurrently.
+    static COUNTING: Cell<bool> = Cell::new(false);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.try_with(|c| c.get()).unwrap_or(false) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn write_text_no_alloc() -> io::Result<()> {
+    let text = "<&>\"'•".repeat(10_000);
+
+    let mut w = XmlWriter::new(io::sink(), Options::default());
+    w.start_element("p")?;
+    w.write_attribute("x", "5")?;
+    w.write_text("text")?;
+
+    COUNTING.with(|c| c.set(true));
+    for _ in 0..100 {
+        w.write_text(&text)?;
+        w.write_text_fmt(format_args!("{}{}", text, 5))?;
+    }
+    COUNTING.with(|c| c.set(false));
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), 0);
+
+    w.end_document()?;
+    Ok(())
+}
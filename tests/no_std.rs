@@ -0,0 +1,61 @@
+#![cfg(not(feature = "std"))]
+
+use std::fmt::{self, Write};
+use xmlwriter::{Options, XmlWriter};
+
+#[test]
+fn write_string_01() -> fmt::Result {
+    let mut w = XmlWriter::new(String::new(), Options::default());
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "q\"")?;
+    w.start_element("text")?;
+    w.write_text("<&>")?;
+    w.end_element()?;
+    w.write_comment("test")?;
+    assert_eq!(
+        w.end_document()?,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<svg id="q&quot;">
+    <text>
+        &lt;&amp;&gt;
+    </text>
+    <!--test-->
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_string_02() -> fmt::Result {
+    let mut w = XmlWriter::new(String::new(), Options::default());
+    w.start_element("path")?;
+    w.write_attribute_raw("d", |writer| writer.write_str("M 10 20 L 30 40"))?;
+    w.start_element("script")?;
+    w.write_cdata_text("a<b")?;
+    assert_eq!(
+        w.end_document()?,
+        "<path d=\"M 10 20 L 30 40\">
+    <script><![CDATA[
+        a<b
+    ]]></script>
+</path>
+"
+    );
+    Ok(())
+}
+
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Err(fmt::Error)
+    }
+}
+
+#[test]
+fn write_error_01() {
+    let mut w = XmlWriter::new(FailingWriter, Options::default());
+    assert_eq!(w.write_comment_fmt(format_args!("{}", 5)), Err(fmt::Error));
+}
@@ -1,3 +1,5 @@
+#![cfg(feature = "std")]
+
 use std::{
     io::{self, Write},
     str::from_utf8,
@@ -49,6 +51,19 @@ fn write_element_03() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+#[should_panic(expected = "end_element called before any element was started")]
+fn write_element_04() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.end_element()
+        .expect("we'll panic before even returning a Result");
+}
+
 #[test]
 fn write_element_05() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
@@ -85,6 +100,115 @@ fn write_element_06() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn write_element_07() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    for (prefix, local) in &[("svg", "svg"), ("svg", "g")] {
+        let name = format!("{}:{}", prefix, local);
+        w.start_element(&name)?;
+    }
+    w.end_element()?;
+    w.start_element(&String::from("xlink:a"))?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg:svg>
+    <svg:g/>
+    <xlink:a/>
+</svg:svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_element_08() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.start_element("g")?;
+    w.start_element("rect")?;
+    w.end_elements(3)?;
+    w.start_element("rect")?;
+    w.end_elements(5)?; // Should not panic.
+    text_eq!(
+        w.end_document()?,
+        r#"<svg>
+    <g>
+        <g>
+            <rect/>
+        </g>
+    </g>
+    <rect/>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_element_09() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("data")?;
+    w.write_attribute("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance")?;
+    w.write_nil_element("foo")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<data xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+    <foo xsi:nil="true"/>
+</data>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_element_10() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    let svg = w.start_element_token("svg")?;
+    let g = w.start_element_token("g")?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.end_element_token(g)?;
+    w.end_element_token(svg)?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg>
+    <g>
+        <rect/>
+    </g>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "element token doesn't match the current element")]
+fn write_element_11() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    let svg = w
+        .start_element_token("svg")
+        .expect("no error expected here!");
+    let _g = w.start_element_token("g").expect("no error expected here!");
+    w.end_element_token(svg)
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_element_12() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.end_element()?;
+    w.end_element()?; // Should not panic.
+    text_eq!(w.end_document()?, "<svg/>\n");
+    Ok(())
+}
+
 #[test]
 #[should_panic(expected = "must be called after start_element()")]
 fn write_attribute_01() {
@@ -215,107 +339,154 @@ fn write_attribute_09() -> io::Result<()> {
 }
 
 #[test]
-fn write_declaration_01() -> io::Result<()> {
+fn write_attribute_10() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute_lossy("id", b"a&\xF0\x90\x80b\xFF\"")?;
+    w.write_attribute_lossy("class", "•".as_bytes())?;
+    w.end_element()?;
     text_eq!(
         w.end_document()?,
-        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n"
+        "<svg id=\"a&amp;\u{FFFD}b\u{FFFD}&quot;\" class=\"•\"/>\n"
     );
     Ok(())
 }
 
 #[test]
-#[should_panic(expected = "declaration was already written")]
-fn write_declaration_02() {
+fn write_attribute_11() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_declaration()
-        .expect("we should only be panicking on the next line!");
-    w.write_declaration()
-        .expect("we'll panic before even returning a Result"); // declaration must be written once
+    w.start_element("svg")?;
+    w.write_attribute("x", Some(5))?;
+    w.write_attribute("y", None::<i32>)?;
+    w.write_attribute("id", "q")?;
+    w.write_attribute("class", Some("a&b"))?;
+    w.end_element()?;
+    text_eq!(
+        w.end_document()?,
+        "<svg x=\"5\" id=\"q\" class=\"a&amp;b\"/>\n"
+    );
+    Ok(())
 }
 
 #[test]
-// declaration was already written
-#[should_panic(expected = "declaration was already written")]
-fn write_declaration_03() {
+#[should_panic(expected = "must be called after start_element()")]
+fn write_attribute_12() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test").expect("no error expected here!");
-    w.write_declaration()
-        .expect("we'll panic before even returning a Result"); // declaration must be written first
+    w.write_attribute("id", None::<&str>)
+        .expect("no IO error since we're supposed to panic first");
 }
 
 #[test]
-fn write_single_quote_01() -> io::Result<()> {
-    let opt = Options {
-        use_single_quote: true,
-        ..Options::default()
-    };
+fn write_attribute_13() -> io::Result<()> {
+    use std::fmt::Write;
 
-    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
-    w.write_declaration()?;
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.write_attribute_from("data-snippet", |buf| {
+        write!(buf, "<rect x=\"{}\"/>", 5)?;
+        buf.push_str(" & more");
+        Ok(())
+    })?;
+    w.write_attribute_from("id", |buf| buf.write_str("q"))?;
+    w.end_element()?;
     text_eq!(
         w.end_document()?,
-        "<?xml version='1.0' encoding='UTF-8' standalone='no'?>\n"
+        "<svg data-snippet=\"&lt;rect x=&quot;5&quot;/&gt; &amp; more\" id=\"q\"/>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_single_quote_02() -> io::Result<()> {
-    let opt = Options {
-        use_single_quote: true,
-        ..Options::default()
-    };
-
-    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
-    w.start_element("p")?;
-    w.write_attribute("a", "b")?;
-    w.end_element()?;
-    text_eq!(w.end_document()?, "<p a='b'/>\n");
-    Ok(())
+fn write_attribute_14() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg").expect("no error expected here!");
+    assert!(w
+        .write_attribute_from("id", |_| Err(std::fmt::Error))
+        .is_err());
 }
 
 #[test]
-fn write_comment_01() -> io::Result<()> {
+fn write_attribute_15() -> io::Result<()> {
+    use std::borrow::Cow;
+
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test")?;
     w.start_element("svg")?;
+    w.write_attribute_cow("id", Cow::Borrowed("a&b"))?;
+    w.write_attribute_cow("class", Cow::Owned(format!("{}\"", 5)))?;
+    w.end_element()?;
     text_eq!(
         w.end_document()?,
-        r#"<!--test-->
-<svg/>
-"#
+        "<svg id=\"a&amp;b\" class=\"5&quot;\"/>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_comment_02() -> io::Result<()> {
+fn write_attribute_16() -> io::Result<()> {
+    let classes = vec![
+        String::from("foo"),
+        String::from("bar"),
+        String::from("baz"),
+    ];
+
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("svg")?;
-    w.write_comment("test")?;
+    w.start_element("div")?;
+    w.write_token_list_attribute("class", &classes)?;
+    w.write_token_list_attribute("data-values", (1..4).map(|n| n * 2))?;
+    w.write_token_list_attribute("data-escaped", &["a&b", "\""])?;
+    w.write_token_list_attribute("data-empty", Vec::<String>::new())?;
+    w.end_element()?;
     text_eq!(
         w.end_document()?,
-        r#"<svg>
-    <!--test-->
-</svg>
-"#
+        "<div class=\"foo bar baz\" data-values=\"2 4 6\" \
+         data-escaped=\"a&amp;b &quot;\" data-empty=\"\"/>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_comment_03() -> io::Result<()> {
+fn write_attribute_17() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("foo").expect("no error expected here!");
+    w.write_attribute_raw_checked("x", |writer| writer.write_all(b"a&b"))
+        .expect("no error expected here!");
+    let err = w
+        .write_attribute_raw_checked("y", |writer| writer.write_all(b"a<b"))
+        .expect_err("`<` must be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+#[should_panic(expected = "cannot write attributes after children have been added to this element")]
+fn write_attribute_18() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg").expect("no error expected here!");
+    w.start_element("rect").expect("no error expected here!");
+    w.end_element().expect("no error expected here!");
+    w.write_attribute("id", "q")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_attribute_19() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test")?;
     w.start_element("svg")?;
-    w.write_comment("test")?;
+    w.write_attribute("a", "'\"")?;
+    w.set_use_single_quote(true);
+    w.write_attribute("b", "'\"")?;
+    w.start_element("g")?;
+    w.write_attribute_fmt("c", format_args!("{}", "'\""))?;
+    w.set_use_single_quote(false);
+    w.write_attribute_cow("d", "'\"".into())?;
     text_eq!(
         w.end_document()?,
-        r#"<!--test-->
-<svg>
-    <!--test-->
+        r#"<svg a="'&quot;" b='&apos;"'>
+    <g c='&apos;"' d="'&quot;"/>
 </svg>
 "#
     );
@@ -323,392 +494,3894 @@ fn write_comment_03() -> io::Result<()> {
 }
 
 #[test]
-fn write_comment_04() -> io::Result<()> {
+#[should_panic(expected = "attribute is not closed, call end_attribute() first")]
+fn write_attribute_20() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test")?;
+    w.start_element("svg")
+        .expect("we'll panic before even returning a Result");
+    w.begin_attribute("a")
+        .expect("we'll panic before even returning a Result");
+    w.set_use_single_quote(true);
+}
+
+#[test]
+fn write_attribute_21() -> io::Result<()> {
+    let opt = Options {
+        trim_trailing_whitespace: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
     w.start_element("svg")?;
-    w.start_element("rect")?;
-    w.write_comment("test")?;
-    text_eq!(
-        w.end_document()?,
-        r#"<!--test-->
-<svg>
-    <rect>
-        <!--test-->
-    </rect>
-</svg>
-"#
-    );
+    w.write_attribute_raw("x", |writer: &mut Vec<u8>| {
+        writer.extend_from_slice(b"1 ");
+        Ok(())
+    })?;
+    w.write_attribute_raw_checked("y", |writer| writer.write_all(b"2 "))?;
+    text_eq!(w.end_document()?, "<svg x=\"1 \" y=\"2 \"/>\n");
     Ok(())
 }
 
 #[test]
-fn write_comment_05() -> io::Result<()> {
+#[should_panic(expected = "write_attribute_raw() cannot be used while the output is held back")]
+fn write_attribute_22() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_attribute_schema_order(&["id"]);
+    w.start_element("svg")
+        .expect("we'll panic before even returning a Result");
+    w.write_attribute_raw("x", |writer| writer.write_all(b"1"))
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_attributes_map_01() -> io::Result<()> {
+    let mut map = std::collections::BTreeMap::new();
+    map.insert("y".to_string(), 20);
+    map.insert("x".to_string(), 10);
+    map.insert("width".to_string(), -5);
+
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test")?;
-    w.start_element("svg")?;
-    w.write_comment("test")?;
     w.start_element("rect")?;
-    w.end_element()?;
+    w.write_attributes_map(map)?;
+    w.start_element("text")?;
+    w.write_attributes_map(vec![("z", "a<b"), ("a", "\"c\"")])?;
     text_eq!(
         w.end_document()?,
-        r#"<!--test-->
-<svg>
-    <!--test-->
-    <rect/>
-</svg>
+        r#"<rect width="-5" x="10" y="20">
+    <text z="a&lt;b" a="&quot;c&quot;"/>
+</rect>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_comment_06() -> io::Result<()> {
+fn write_declaration_01() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_comment("test")?;
-    w.start_element("svg")?;
-    w.start_element("rect")?;
-    w.end_element()?;
-    w.write_comment("test")?;
+    w.write_declaration()?;
     text_eq!(
         w.end_document()?,
-        r#"<!--test-->
-<svg>
-    <rect/>
-    <!--test-->
-</svg>
-"#
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_comment_07() -> io::Result<()> {
+#[should_panic(expected = "declaration was already written")]
+fn write_declaration_02() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("svg")?;
-    w.end_element()?;
-    w.write_comment("test")?;
+    w.write_declaration()
+        .expect("we should only be panicking on the next line!");
+    w.write_declaration()
+        .expect("we'll panic before even returning a Result"); // declaration must be written once
+}
+
+#[test]
+// declaration was already written
+#[should_panic(expected = "declaration was already written")]
+fn write_declaration_03() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_comment("test").expect("no error expected here!");
+    w.write_declaration()
+        .expect("we'll panic before even returning a Result"); // declaration must be written first
+}
+
+#[test]
+fn write_html5_doctype_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_html5_doctype()?;
+    w.start_element("html")?;
+    w.write_attribute("lang", "en")?;
     text_eq!(
         w.end_document()?,
-        r#"<svg/>
-<!--test-->
+        r#"<!DOCTYPE html>
+<html lang="en"/>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_comment_08() -> io::Result<()> {
+fn write_html5_doctype_02() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
     w.write_comment("test")?;
-    w.write_comment("test")?;
-    w.write_comment("test")?;
+    w.write_html5_doctype()?;
+    w.start_element("html")?;
     text_eq!(
         w.end_document()?,
         r#"<!--test-->
-<!--test-->
-<!--test-->
+<!DOCTYPE html>
+<html/>
 "#
     );
     Ok(())
 }
 
 #[test]
-#[should_panic(expected = "must be called after start_element()")]
-fn write_text_01() {
+#[should_panic(expected = "doctype must be written before the root element")]
+fn write_html5_doctype_03() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.write_text("text")
-        .expect("should panic before giving us a Result"); // Should be called after start_element()
+    w.start_element("html").expect("no error expected here!");
+    w.end_element().expect("no error expected here!");
+    w.write_html5_doctype()
+        .expect("we'll panic before even returning a Result");
 }
 
 #[test]
-#[should_panic(expected = "must be called after start_element()")]
-fn write_text_03() {
+#[should_panic(expected = "doctype was already written")]
+fn write_html5_doctype_04() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p").expect("should not fail");
-    w.end_element().expect("should not fail");
-    w.write_text("text")
-        .expect("should panic before giving us a Result"); // Should be called after start_element()
+    w.write_html5_doctype().expect("no error expected here!");
+    w.write_html5_doctype()
+        .expect("we'll panic before even returning a Result");
 }
 
 #[test]
-fn write_text_04() -> io::Result<()> {
+#[should_panic(expected = "doctype was already written")]
+fn write_html5_doctype_05() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("text")?;
-    w.write_text("text")?;
+    w.write_doctype_full("html", None, None, None::<Subset>)
+        .expect("no error expected here!");
+    w.write_html5_doctype()
+        .expect("we'll panic before even returning a Result");
+}
+
+type Subset = fn(&mut XmlWriter<Vec<u8>>) -> io::Result<()>;
+
+#[test]
+fn write_doctype_full_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_doctype_full(
+        "svg",
+        Some("-//W3C//DTD SVG 1.1//EN"),
+        Some("http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd"),
+        Some(|w: &mut XmlWriter<Vec<u8>>| {
+            w.write_raw_fmt(format_args!(
+                "<!ENTITY ns \"{}\">",
+                "http://www.w3.org/2000/svg"
+            ))
+        }),
+    )?;
+    w.start_element("svg")?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    text
-    text
-</p>
+        r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd" [<!ENTITY ns "http://www.w3.org/2000/svg">]>
+<svg/>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_05() -> io::Result<()> {
+fn write_doctype_full_02() -> io::Result<()> {
+    let cases = [
+        ("a", None, None, "<!DOCTYPE a>\n"),
+        (
+            "b",
+            None,
+            Some("say \"hi\".dtd"),
+            "<!DOCTYPE b SYSTEM 'say \"hi\".dtd'>\n",
+        ),
+        (
+            "c",
+            Some("pub"),
+            Some("sys"),
+            "<!DOCTYPE c PUBLIC \"pub\" \"sys\">\n",
+        ),
+    ];
+
+    for &(name, public_id, system_id, expected) in &cases {
+        let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+        w.write_doctype_full(name, public_id, system_id, None::<Subset>)?;
+        text_eq!(w.end_document()?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn write_doctype_full_03() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("text")?;
+    let err = w
+        .write_doctype_full("svg", Some("pub"), None, None::<Subset>)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = w
+        .write_doctype_full("svg", Some("\"pub\""), Some("sys"), None::<Subset>)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = w
+        .write_doctype_full("svg", None, Some("\"'"), None::<Subset>)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    w.start_element("svg")?;
+    text_eq!(w.end_document()?, "<svg/>\n");
+    Ok(())
+}
+
+#[test]
+fn write_doctype_full_04() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    for name in &["", "svg>", "my svg", "1svg"] {
+        let err = w
+            .write_doctype_full(name, None, None, None::<Subset>)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    w.write_doctype_full("svg:svg", None, None, None::<Subset>)?;
+    w.start_element("svg:svg")?;
+    text_eq!(w.end_document()?, "<!DOCTYPE svg:svg>\n<svg:svg/>\n");
+    Ok(())
+}
+
+#[test]
+fn write_declaration_04() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_bom()?;
+    w.write_declaration_with_encoding("UTF-16")?;
+    w.start_element("svg")?;
+    let data = w.end_document()?;
+    assert_eq!(&data[..3], b"\xEF\xBB\xBF");
     text_eq!(
-        w.end_document()?,
-        r#"<p>
-    text
-</p>
-"#
+        data[3..].to_vec(),
+        "<?xml version=\"1.0\" encoding=\"UTF-16\" standalone=\"no\"?>\n<svg/>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_text_06() -> io::Result<()> {
+#[should_panic(expected = "invalid encoding name")]
+fn write_declaration_05() {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("text")?;
-    w.start_element("p")?;
-    w.write_text("text")?;
+    w.write_declaration_with_encoding("\"UTF-8")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_declaration_06() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.write_declaration_with_encoding("UTF 8")
+    }));
+    assert!(result.is_err());
+    // Nothing was written before the panic.
+    w.start_element("svg")?;
+    text_eq!(w.end_document()?, "<svg/>\n");
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "BOM must be written at the start of the document")]
+fn write_bom_01() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_declaration().expect("no error expected here!");
+    w.write_bom()
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_single_quote_01() -> io::Result<()> {
+    let opt = Options {
+        use_single_quote: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    text
-    <p>
-        text
-    </p>
-</p>
-"#
+        "<?xml version='1.0' encoding='UTF-8' standalone='no'?>\n"
     );
     Ok(())
 }
 
 #[test]
-fn write_text_07() -> io::Result<()> {
-    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("div")?;
-    w.start_element("p")?;
-    w.write_text("text")?;
+fn write_single_quote_02() -> io::Result<()> {
+    let opt = Options {
+        use_single_quote: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
     w.start_element("p")?;
-    w.write_text("text")?;
+    w.write_attribute("a", "b")?;
+    w.end_element()?;
+    text_eq!(w.end_document()?, "<p a='b'/>\n");
+    Ok(())
+}
+
+#[test]
+fn options_debug_01() {
+    let opt = Options {
+        attribute_to_element_if: Some(|_, value| value.len() > 10),
+        ..Options::default()
+    };
+
+    let debug = format!("{:?}", opt);
+    assert!(debug.starts_with("Options { use_single_quote: false, "));
+    assert!(debug.contains(", attribute_to_element_if: Some(\"..\")"));
+
+    let debug = format!("{:?}", Options::default());
+    assert!(debug.contains(", attribute_to_element_if: None"));
+}
+
+#[test]
+fn write_comment_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_comment("test")?;
+    w.start_element("svg")?;
     text_eq!(
         w.end_document()?,
-        r#"<div>
-    <p>
-        text
-        <p>
-            text
-        </p>
-    </p>
-</div>
+        r#"<!--test-->
+<svg/>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_08() -> io::Result<()> {
+fn write_comment_02() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("<")?;
+    w.start_element("svg")?;
+    w.write_comment("test")?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    &lt;
-</p>
+        r#"<svg>
+    <!--test-->
+</svg>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_09() -> io::Result<()> {
+fn write_comment_03() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("<&>")?;
+    w.write_comment("test")?;
+    w.start_element("svg")?;
+    w.write_comment("test")?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    &lt;&amp;&gt;
-</p>
+        r#"<!--test-->
+<svg>
+    <!--test-->
+</svg>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_10() -> io::Result<()> {
+fn write_comment_04() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("&lt;")?;
+    w.write_comment("test")?;
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    w.write_comment("test")?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    &amp;lt;
-</p>
+        r#"<!--test-->
+<svg>
+    <rect>
+        <!--test-->
+    </rect>
+</svg>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_11() -> io::Result<()> {
+fn write_comment_05() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_text("text")?;
-    w.start_element("p")?;
+    w.write_comment("test")?;
+    w.start_element("svg")?;
+    w.write_comment("test")?;
+    w.start_element("rect")?;
     w.end_element()?;
-    w.write_text("text")?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    text
-    <p/>
-    text
-</p>
+        r#"<!--test-->
+<svg>
+    <!--test-->
+    <rect/>
+</svg>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_12() -> io::Result<()> {
+fn write_comment_06() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.write_attribute("att", "fooo&bar&&&&amp;&amp;&baz&&&")?;
-    w.write_text("fooo&bar&&&&amp;&amp;&baz&&&")?;
+    w.write_comment("test")?;
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.write_comment("test")?;
     text_eq!(
         w.end_document()?,
-        r#"<p att="fooo&amp;bar&amp;&amp;&amp;&amp;amp;&amp;amp;&amp;baz&amp;&amp;&amp;">
-    fooo&amp;bar&amp;&amp;&amp;&amp;amp;&amp;amp;&amp;baz&amp;&amp;&amp;
-</p>
+        r#"<!--test-->
+<svg>
+    <rect/>
+    <!--test-->
+</svg>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn write_text_cdata() -> io::Result<()> {
+fn write_comment_07() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("script")?;
-    w.write_cdata_text("function cmp(a,b) { return (a<b)?-1:(a>b)?1:0; }")?;
+    w.start_element("svg")?;
+    w.end_element()?;
+    w.write_comment("test")?;
     text_eq!(
         w.end_document()?,
-        "<script><![CDATA[
-    function cmp(a,b) { return (a<b)?-1:(a>b)?1:0; }
-]]></script>
-"
+        r#"<svg/>
+<!--test-->
+"#
     );
     Ok(())
 }
 
 #[test]
-fn write_preserve_text_01() -> io::Result<()> {
+fn write_comment_08() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.set_preserve_whitespaces(true);
-    w.start_element("p")?;
-    w.write_text("text")?;
-    w.start_element("p")?;
-    w.end_element()?;
-    w.write_text("text")?;
-    text_eq!(w.end_document()?, "<p>text<p/>text</p>");
+    w.write_comment("test")?;
+    w.write_comment("test")?;
+    w.write_comment("test")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<!--test-->
+<!--test-->
+<!--test-->
+"#
+    );
     Ok(())
 }
 
 #[test]
-fn write_preserve_text_02() -> io::Result<()> {
+fn write_comment_09() -> io::Result<()> {
+    struct Version(u8, u8);
+
+    impl std::fmt::Display for Version {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "version {}.{}", self.0, self.1)
+        }
+    }
+
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("p")?;
-    w.start_element("p")?;
-    w.set_preserve_whitespaces(true);
-    w.write_text("text")?;
-    w.start_element("p")?;
-    w.end_element()?;
-    w.write_text("text")?;
-    w.end_element()?;
-    w.set_preserve_whitespaces(false);
+    w.write_comment_display(&5)?;
+    w.write_comment_display(&Version(1, 2))?;
     text_eq!(
         w.end_document()?,
-        r#"<p>
-    <p>text<p/>text</p>
-</p>
+        r#"<!--5-->
+<!--version 1.2-->
 "#
     );
     Ok(())
 }
 
 #[test]
-fn attrs_indent_01() -> io::Result<()> {
+#[should_panic(expected = "cannot write attributes after children have been added to this element")]
+fn write_comment_10() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg").expect("no error expected here!");
+    w.write_comment("test").expect("no error expected here!");
+    // The comment closes the attributes list by default.
+    w.write_attribute("id", "q")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+#[should_panic(expected = "comment cannot be written before the element attributes are finished")]
+fn write_comment_11() {
     let opt = Options {
-        attributes_indent: xmlwriter::Indent::Spaces(2),
+        open_element_on_comment: false,
         ..Options::default()
     };
 
     let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
-    w.start_element("rect")?;
-    w.write_attribute("x", "5")?;
-    w.start_element("rect")?;
-    w.write_attribute("x", "10")?;
+    w.start_element("svg").expect("no error expected here!");
+    w.write_comment("test")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_comment_12() -> io::Result<()> {
+    let opt = Options {
+        open_element_on_comment: false,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_comment("test")?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "q")?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.write_comment("test")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<!--test-->
+<svg id="q">
+    <rect/>
+    <!--test-->
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_comment_13() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_comment("a--b---c-")?;
+    w.write_comment(">test")?;
+    w.write_comment_fmt(format_args!("{}{}", "a-", "-b-"))?;
+    w.write_comment("")?;
+    text_eq!(
+        w.end_document()?,
+        "<!--a- -b- - -c- -->
+<!-- >test-->
+<!--a- -b- -->
+<!---->
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_comment_14() -> io::Result<()> {
+    // Comments are written inline when whitespaces are preserved.
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("html")?;
+    w.start_element("p")?;
+    w.set_preserve_whitespaces(true);
+    w.write_comment("first")?;
+    w.write_text("text")?;
+    w.write_comment("second")?;
+    w.start_element("b")?;
+    w.write_comment("third")?;
+    w.end_element()?;
+    w.end_element()?;
+    w.set_preserve_whitespaces(false);
+    w.write_comment("fourth")?;
+    text_eq!(
+        w.end_document()?,
+        "<html>
+    <p><!--first-->text<!--second--><b><!--third--></b></p>
+    <!--fourth-->
+</html>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_comment_15() -> io::Result<()> {
+    let opt = Options {
+        comment_wrap_width: Some(24),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("config")?;
+    w.write_comment("short")?;
+    w.write_comment("The quick  brown fox jumps over the lazy -- dog -")?;
+    w.start_element("item")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<config>
+    <!--short-->
+    <!--
+        The quick brown
+        fox jumps over
+        the lazy - - dog
+        -
+    -->
+    <item/>
+</config>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_comment_16() -> io::Result<()> {
+    use std::fmt::Write;
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.write_comment_from(|buf| {
+        buf.push_str("size: ");
+        write!(buf, "{}x{}", 10, 20)?;
+        buf.push_str(" --units-");
+        Ok(())
+    })?;
+    assert!(w.write_comment_from(|_| Err(std::fmt::Error)).is_err());
+    text_eq!(
+        w.end_document()?,
+        "<svg>\n    <!--size: 10x20 - -units- -->\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_comment_17() -> io::Result<()> {
+    // Tabs are still written as is, but count as `tab_width` columns.
+    let write = |tab_width| -> io::Result<Vec<u8>> {
+        let opt = Options {
+            indent: xmlwriter::Indent::Tabs,
+            comment_wrap_width: Some(24),
+            tab_width,
+            ..Options::default()
+        };
+
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        w.start_element("config")?;
+        w.write_comment("The quick brown fox")?;
+        w.end_document()
+    };
+
+    text_eq!(
+        write(4)?,
+        "<config>\n\t<!--\n\t\tThe quick brown\n\t\tfox\n\t-->\n</config>\n"
+    );
+    text_eq!(
+        write(8)?,
+        "<config>\n\t<!--\n\t\tThe\n\t\tquick\n\t\tbrown\n\t\tfox\n\t-->\n</config>\n"
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "must be called after start_element()")]
+fn write_text_01() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_text("text")
+        .expect("should panic before giving us a Result"); // Should be called after start_element()
+}
+
+#[test]
+#[should_panic(expected = "must be called after start_element()")]
+fn write_text_03() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p").expect("should not fail");
+    w.end_element().expect("should not fail");
+    w.write_text("text")
+        .expect("should panic before giving us a Result"); // Should be called after start_element()
+}
+
+#[test]
+#[should_panic(expected = "text not allowed at document level")]
+fn write_text_strict_01() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration().expect("no error expected here!");
+    w.write_text("text")
+        .expect("should panic before giving us a Result");
+}
+
+#[test]
+#[should_panic(expected = "text not allowed at document level")]
+fn write_text_strict_02() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p").expect("should not fail");
+    w.end_element().expect("should not fail");
+    w.write_text("text")
+        .expect("should panic before giving us a Result");
+}
+
+#[test]
+fn write_text_strict_03() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    text
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_04() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    text
+    text
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_05() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    text
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_06() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.start_element("p")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    text
+    <p>
+        text
+    </p>
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_07() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("div")?;
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.start_element("p")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<div>
+    <p>
+        text
+        <p>
+            text
+        </p>
+    </p>
+</div>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_08() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("<")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    &lt;
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_09() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("<&>")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    &lt;&amp;&gt;
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_10() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("&lt;")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    &amp;lt;
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_11() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.start_element("p")?;
+    w.end_element()?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    text
+    <p/>
+    text
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_12() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_attribute("att", "fooo&bar&&&&amp;&amp;&baz&&&")?;
+    w.write_text("fooo&bar&&&&amp;&amp;&baz&&&")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p att="fooo&amp;bar&amp;&amp;&amp;&amp;amp;&amp;amp;&amp;baz&amp;&amp;&amp;">
+    fooo&amp;bar&amp;&amp;&amp;&amp;amp;&amp;amp;&amp;baz&amp;&amp;&amp;
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_wrap_01() -> io::Result<()> {
+    let opt = Options {
+        text_wrap_width: Some(24),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("config")?;
+    w.start_element("description")?;
+    w.write_text("The quick  brown fox jumps over the lazy dog & cat.")?;
+    w.end_element()?;
+    w.start_element("script")?;
+    w.write_cdata_text("The quick brown fox jumps over the lazy dog.")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<config>
+    <description>
+        The quick brown
+        fox jumps over
+        the lazy dog &amp;
+        cat.
+    </description>
+    <script><![CDATA[
+        The quick brown fox jumps over the lazy dog.
+    ]]></script>
+</config>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_wrap_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::Tabs,
+        text_wrap_width: Some(20),
+        tab_width: 8,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_text("The quick brown fox")?;
+    text_eq!(w.end_document()?, "<p>\n\tThe quick\n\tbrown fox\n</p>\n");
+    Ok(())
+}
+
+#[test]
+fn write_decimal_text_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("values")?;
+    w.write_decimal_text(1e-9, 12)?;
+    w.write_decimal_text(1e20, 0)?;
+    w.write_decimal_text(-2.5, 3)?;
+    text_eq!(
+        w.end_document()?,
+        r#"<values>
+    0.000000001000
+    100000000000000000000
+    -2.500
+</values>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_decimal_text_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("values")?;
+    for &value in &[std::f64::NAN, std::f64::INFINITY, std::f64::NEG_INFINITY] {
+        let err = w
+            .write_decimal_text(value, 2)
+            .expect_err("non-finite values must be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    text_eq!(w.end_document()?, "<values/>");
+    Ok(())
+}
+
+fn text_with_gt_escaping(mode: xmlwriter::TextGtEscaping) -> io::Result<String> {
+    let opt = Options {
+        text_gt_escaping: mode,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_attribute("a", "a>b")?;
+    w.write_text("a>b")?;
+    w.write_text("a]]>b")?;
+    w.write_text("a] ]>b")?;
+    // Split `]]>` between chunks.
+    w.write_text_fmt(format_args!("{}{}", "a]", "]>b"))?;
+    // A new text node doesn't continue the previous one.
+    w.write_text("a]]")?;
+    w.write_text(">b")?;
+    Ok(String::from_utf8(w.end_document()?).expect("XmlWriter should produce valid UTF8"))
+}
+
+#[test]
+fn text_gt_escaping_01() -> io::Result<()> {
+    assert_eq!(
+        text_with_gt_escaping(xmlwriter::TextGtEscaping::Always)?,
+        r#"<p a="a&gt;b">
+    a&gt;b
+    a]]&gt;b
+    a] ]&gt;b
+    a]]&gt;b
+    a]]
+    &gt;b
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn text_gt_escaping_02() -> io::Result<()> {
+    assert_eq!(
+        text_with_gt_escaping(xmlwriter::TextGtEscaping::OnlyAfterBrackets)?,
+        r#"<p a="a&gt;b">
+    a>b
+    a]]&gt;b
+    a] ]>b
+    a]]&gt;b
+    a]]
+    >b
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn text_gt_escaping_03() -> io::Result<()> {
+    assert_eq!(
+        text_with_gt_escaping(xmlwriter::TextGtEscaping::Never)?,
+        r#"<p a="a&gt;b">
+    a>b
+    a]]>b
+    a] ]>b
+    a]]>b
+    a]]
+    >b
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_entities_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::typographic());
+    w.start_element("para")?;
+    w.write_attribute("title", "A—B")?;
+    w.write_text("“Wait—what?” & ‘no’…")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<para title="A—B">
+    &ldquo;Wait&mdash;what?&rdquo; &amp; &lsquo;no&rsquo;&hellip;
+</para>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_entities_02() -> io::Result<()> {
+    let opt = Options {
+        entity_map: Some(&[('•', "bull")]),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_text("a•b—c")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    a&bull;b—c
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_13() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("doc")?;
+    w.start_element("pre")?;
+    w.write_preformatted_text("fn main() {\n  let a = 1 < 2;\n\n  println!(\"{}\", a);\n}")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<doc>
+    <pre>
+        fn main() {
+          let a = 1 &lt; 2;
+
+          println!("{}", a);
+        }
+    </pre>
+</doc>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_entities_03() -> io::Result<()> {
+    let opt = Options {
+        entity_map: Some(&[('&', "#38")]),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_attribute("a", "a&<b")?;
+    w.write_text("a&<b")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<p a="a&#38;&lt;b">
+    a&#38;&lt;b
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_cdata() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("script")?;
+    w.write_cdata_text("function cmp(a,b) { return (a<b)?-1:(a>b)?1:0; }")?;
+    text_eq!(
+        w.end_document()?,
+        "<script><![CDATA[
+    function cmp(a,b) { return (a<b)?-1:(a>b)?1:0; }
+]]></script>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_cdata_try() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("script")?;
+    let err = w
+        .try_write_cdata_text("a]]>b")
+        .expect_err("`]]>` must be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "CDATA text must not contain `]]>'");
+    w.try_write_cdata_text("a]]b")?;
+    text_eq!(
+        w.end_document()?,
+        "<script><![CDATA[
+    a]]b
+]]></script>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_preserve_text_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_preserve_whitespaces(true);
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.start_element("p")?;
+    w.end_element()?;
+    w.write_text("text")?;
+    text_eq!(w.end_document()?, "<p>text<p/>text</p>");
+    Ok(())
+}
+
+#[test]
+fn write_preserve_text_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.start_element("p")?;
+    w.set_preserve_whitespaces(true);
+    w.write_text("text")?;
+    w.start_element("p")?;
+    w.end_element()?;
+    w.write_text("text")?;
+    w.end_element()?;
+    w.set_preserve_whitespaces(false);
+    text_eq!(
+        w.end_document()?,
+        r#"<p>
+    <p>text<p/>text</p>
+</p>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_preserve_text_03() -> io::Result<()> {
+    // Empty text still opens the element.
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_preserve_whitespaces(true);
+    w.start_element("div")?;
+    w.start_element("p")?;
+    w.write_text("")?;
+    w.end_element()?;
+    text_eq!(w.end_document()?, "<div><p></p></div>");
+
+    let opt = Options {
+        skip_empty_text: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.set_preserve_whitespaces(true);
+    w.start_element("div")?;
+    w.start_element("p")?;
+    w.write_text("")?;
+    w.end_element()?;
+    w.start_element("p")?;
+    w.write_text("")?;
+    w.write_text("text")?;
+    text_eq!(w.end_document()?, "<div><p/><p>text</p></div>");
+    Ok(())
+}
+
+#[test]
+fn write_preserve_text_04() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_preserve_whitespaces(true);
+    w.start_element("p")?;
+    w.write_text("Hello,")?;
+    w.write_whitespace(" ")?;
+    w.start_element("b")?;
+    w.write_text("big")?;
+    w.end_element()?;
+    w.write_whitespace(" ")?;
+    w.start_element("b")?;
+    w.write_text("World")?;
+    w.end_element()?;
+    assert!(w.write_whitespace(" x").is_err());
+    text_eq!(w.end_document()?, "<p>Hello, <b>big</b> <b>World</b></p>");
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "write_whitespace requires preserved whitespaces")]
+fn write_preserve_text_06() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p").expect("no error expected here!");
+    w.write_whitespace(" ")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_preserve_text_05() -> io::Result<()> {
+    // Preserved content keeps its own line endings.
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("mail")?;
+    w.start_element("body")?;
+    w.set_preserve_whitespaces(true);
+    w.write_text("Hello,\r\n\r\nWorld\r\n")?;
+    w.write_whitespace("\r\n")?;
+    w.end_element()?;
+    w.set_preserve_whitespaces(false);
+    w.start_element("footer")?;
+    text_eq!(
+        w.end_document()?,
+        "<mail>\n    <body>Hello,\r\n\r\nWorld\r\n\r\n</body>\n    <footer/>\n</mail>\n"
+    );
+
+    let opt = Options {
+        line_ending: xmlwriter::LineEnding::CrLf,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("mail")?;
+    w.start_element("body")?;
+    w.set_preserve_whitespaces(true);
+    w.write_text("Hello,\nWorld\n")?;
+    w.end_element()?;
+    w.set_preserve_whitespaces(false);
+    w.start_element("footer")?;
+    text_eq!(
+        w.end_document()?,
+        "<mail>\r\n    <body>Hello,\nWorld\n</body>\r\n    <footer/>\r\n</mail>\r\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn attrs_indent_01() -> io::Result<()> {
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("rect")?;
+    w.write_attribute("x", "5")?;
+    w.start_element("rect")?;
+    w.write_attribute("x", "10")?;
+    w.write_attribute("y", "15")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<rect
+  x="5">
+    <rect
+      x="10"
+      y="15"/>
+</rect>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn indent_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::Spaces(0),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.start_element("rect")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg>
+<g>
+<rect/>
+</g>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn compact_siblings_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        space_between_siblings_when_compact: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.end_element()?;
+    text_eq!(w.end_document()?, "<svg><rect/> <rect/></svg>");
+    Ok(())
+}
+
+#[test]
+fn compact_siblings_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        space_between_siblings_when_compact: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_comment("test")?;
+    w.start_element("p")?;
+    w.write_text("text")?;
+    w.start_element("b")?;
+    w.write_text("text")?;
+    w.end_element()?;
+    w.write_comment("test")?;
+    text_eq!(
+        w.end_document()?,
+        "<!--test--> <p>text <b>text</b> <!--test--></p>"
+    );
+    Ok(())
+}
+
+#[test]
+fn indent_02() -> io::Result<()> {
+    use xmlwriter::Indent;
+
+    for &(indent, unit) in &[
+        (Indent::Spaces(1), " "),
+        (Indent::Spaces(3), "   "),
+        (Indent::Spaces(8), "        "),
+        (Indent::Tabs, "\t"),
+    ] {
+        let opt = Options {
+            indent,
+            attributes_indent: Indent::Spaces(2),
+            ..Options::default()
+        };
+
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        let mut expected = String::new();
+        for depth in 0..12 {
+            w.start_element("g")?;
+            w.write_attribute("id", &depth)?;
+            if depth > 0 {
+                expected.push_str(">\n");
+            }
+            expected.push_str(&format!(
+                "{0}<g\n{0}  id=\"{1}\"",
+                unit.repeat(depth),
+                depth
+            ));
+        }
+        expected.push_str("/>\n");
+        for depth in (0..11).rev() {
+            expected.push_str(&format!("{}</g>\n", unit.repeat(depth)));
+        }
+
+        text_eq!(w.end_document()?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn attrs_indent_02() -> io::Result<()> {
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.write_attribute("x", "5")?;
+    w.write_comment("test")?;
+    w.start_element("g")?;
+    w.write_attribute("x", "10")?;
     w.write_attribute("y", "15")?;
+    w.write_comment("test")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg
+  x="5">
+    <!--test-->
+    <g
+      x="10"
+      y="15">
+        <!--test-->
+    </g>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn attrs_indent_03() -> io::Result<()> {
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        align_attribute_names: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    w.write_attribute("id", "rect1")?;
+    w.write_attribute("stroke-width", "2")?;
+    w.write_attribute("fill", "none")?;
+    w.end_element()?;
+    w.start_element_nowrap("g")?;
+    w.write_attribute("id", "g1")?;
+    w.write_attribute("fill", "none")?;
+    text_eq!(
+        w.end_document()?,
+        "<svg>
+    <rect
+      id          =\"rect1\"
+      stroke-width=\"2\"
+      fill        =\"none\"/>
+    <g id=\"g1\" fill=\"none\"/>
+</svg>
+"
+    );
+    Ok(())
+}
+
+// At some point I had used split_at() with a byte index but that does not work for multi-bytes
+// characters, so let's that to make sure it isn't reintroduced.
+#[test]
+fn multibytes_escaping_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("test")?;
+    w.write_attribute("foo", "aaa&bbb<ccc•&•>&•")?;
+    w.write_attribute_fmt("bar", format_args!("aaa&bbb<ccc{}&•>&•", '•'))?;
+    w.write_text("aaa&bbb<ccc•&•>&•")?;
+    w.write_text_fmt(format_args!("aaa&bbb<ccc{}&•>&•", '•'))?;
+
+    text_eq!(
+        w.end_document()?,
+        r#"<test foo="aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•" bar="aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•">
+    aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•
+    aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•
+</test>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn disabled_self_close() -> io::Result<()> {
+    let opts = Options {
+        enable_self_closing: false,
+        ..Options::default()
+    };
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opts);
+    w.start_element("empty1")?;
+    w.end_element()?;
+    w.start_element("wrapper")?;
+    w.start_element("empty2")?;
+    w.end_element()?;
+    w.end_element()?;
+
+    text_eq!(
+        w.end_document()?,
+        r#"<empty1>
+</empty1>
+<wrapper>
+    <empty2>
+    </empty2>
+</wrapper>
+"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn disabled_self_close_runtime() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("wrapper")?;
+    w.start_element("empty1")?;
+    w.end_element()?;
+    w.set_self_closing(false);
+    w.start_element("empty2")?;
+    w.end_element()?;
+    w.set_self_closing(true);
+    w.start_element("empty3")?;
+    w.end_element()?;
+
+    text_eq!(
+        w.end_document()?,
+        r#"<wrapper>
+    <empty1/>
+    <empty2>
+    </empty2>
+    <empty3/>
+</wrapper>
+"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn swap_writer_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "q")?;
+    w.start_element("g")?;
+    let mut data = w.swap_writer(Vec::new());
+    w.write_text("text")?;
+    w.start_element("rect")?;
+    w.write_attribute("x", &5)?;
+    data.extend_from_slice(&w.end_document()?);
+
+    text_eq!(
+        data,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<svg id="q">
+    <g>
+        text
+        <rect x="5"/>
+    </g>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn event_observer_01() -> io::Result<()> {
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl xmlwriter::XmlEvents for Recorder {
+        fn on_start_element(&mut self, name: &str, line: usize, column: usize) {
+            self.0
+                .borrow_mut()
+                .push(format!("start {} {}:{}", name, line, column));
+        }
+
+        fn on_end_element(&mut self, name: &str, line: usize, column: usize) {
+            self.0
+                .borrow_mut()
+                .push(format!("end {} {}:{}", name, line, column));
+        }
+
+        fn on_text(&mut self, line: usize, column: usize) {
+            self.0
+                .borrow_mut()
+                .push(format!("text {}:{}", line, column));
+        }
+    }
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_event_observer(Box::new(Recorder(events.clone())));
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "•")?;
+    w.start_element("text")?;
+    w.write_text("text")?;
+    w.end_element()?;
+    w.start_element("rect")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<svg id="•">
+    <text>
+        text
+    </text>
+    <rect/>
+</svg>
+"#
+    );
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "start svg 2:1",
+            "start text 3:5",
+            "text 4:9",
+            "end text 5:5",
+            "start rect 6:5",
+            "end rect 6:10",
+            "end svg 7:1",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn swap_writer_02() -> io::Result<()> {
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl xmlwriter::XmlEvents for Recorder {
+        fn on_start_element(&mut self, name: &str, line: usize, column: usize) {
+            self.0
+                .borrow_mut()
+                .push(format!("start {} {}:{}", name, line, column));
+        }
+    }
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.set_event_observer(Box::new(Recorder(events.clone())));
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.write_text_append("text")?;
+    let mut data = w.swap_writer(Vec::new());
+    w.write_text_append(" more")?;
+    w.start_element("rect")?;
+    let tail = w.end_document()?;
+
+    text_eq!(
+        tail,
+        r#" more
+        <rect/>
+    </g>
+</svg>
+"#
+    );
+    data.extend_from_slice(&tail);
+    text_eq!(
+        data,
+        r#"<svg>
+    <g>
+        text more
+        <rect/>
+    </g>
+</svg>
+"#
+    );
+    assert_eq!(
+        *events.borrow(),
+        vec!["start svg 1:1", "start g 2:5", "start rect 2:9"]
+    );
+    Ok(())
+}
+
+#[test]
+fn namespaces_01() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element_ns(None, "svg")?;
+    w.with_namespace("xlink", "http://www.w3.org/1999/xlink", |w| {
+        w.start_element_ns(Some("xlink"), "a")?;
+        w.start_element_ns(Some("xml"), "b")?;
+        w.end_element()?;
+        w.end_element()
+    })?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg xmlns:xlink="http://www.w3.org/1999/xlink">
+    <xlink:a>
+        <xml:b/>
+    </xlink:a>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "undeclared namespace prefix")]
+fn namespaces_02() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg").expect("no error expected here!");
+    w.with_namespace("xlink", "http://www.w3.org/1999/xlink", |w| w.end_element())
+        .expect("no error expected here!");
+    // Out of the namespace scope.
+    w.start_element_ns(Some("xlink"), "a")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn namespaces_03() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element_ns(Some("svg"), "svg")?;
+    text_eq!(w.end_document()?, "<svg:svg/>\n");
+    Ok(())
+}
+
+#[test]
+fn namespaces_04() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.with_namespace("xlink", "http://www.w3.org/1999/xlink", |w| {
+        w.write_attribute_prefixed("xml", "space", "preserve")?;
+        w.start_element("use")?;
+        w.write_attribute_prefixed("xlink", "href", "#a&b")?;
+        w.write_attribute_prefixed("xlink", "title", None::<&str>)?;
+        w.end_element()
+    })?;
+    text_eq!(
+        w.end_document()?,
+        r##"<svg xmlns:xlink="http://www.w3.org/1999/xlink" xml:space="preserve">
+    <use xlink:href="#a&amp;b"/>
+</svg>
+"##
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "undeclared namespace prefix")]
+fn namespaces_05() {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg").expect("no error expected here!");
+    w.write_attribute_prefixed("xlink", "href", "#a")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_pi_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_declaration()?;
+    w.write_processing_instruction("xml-stylesheet", "type=\"text/xsl\" href=\"a.xsl\"")?;
+    w.start_element("svg")?;
+    w.write_processing_instruction("pi", "")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?xml-stylesheet type="text/xsl" href="a.xsl"?>
+<svg>
+    <?pi?>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "processing instruction data cannot contain '?>'")]
+fn write_pi_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_processing_instruction("pi", "a ?> b")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+#[should_panic(expected = "invalid processing instruction target")]
+fn write_pi_03() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_processing_instruction("XML", "")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn write_xml_model_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_xml_model(
+        "schema.rng?v=1&x=\"2\"",
+        Some("application/xml"),
+        Some("http://relaxng.org/ns/structure/1.0"),
+    )?;
+    w.start_element("book")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<?xml-model href="schema.rng?v=1&amp;x=&quot;2&quot;" type="application/xml" schematypens="http://relaxng.org/ns/structure/1.0"?>
+<book/>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn reuse_depth_stack_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.start_element("rect")?;
+    let (data, stack) = w.into_parts()?;
+    text_eq!(
+        data,
+        "<svg>
+    <g>
+        <rect/>
+    </g>
+</svg>
+"
+    );
+
+    let capacity = stack.capacity();
+    assert!(capacity >= 3);
+
+    let mut w = XmlWriter::from_parts(Vec::<u8>::new(), Options::default(), stack);
+    w.start_element("html")?;
+    w.start_element("body")?;
+    w.write_text("text")?;
+    let (data, stack) = w.into_parts()?;
+    text_eq!(
+        data,
+        "<html>
+    <body>
+        text
+    </body>
+</html>
+"
+    );
+    assert_eq!(stack.capacity(), capacity);
+    Ok(())
+}
+
+#[test]
+fn write_datetime_attribute_01() -> io::Result<()> {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("event")?;
+    w.write_datetime_attribute("a", UNIX_EPOCH)?;
+    w.write_datetime_attribute("b", UNIX_EPOCH + Duration::from_secs(951_782_400))?;
+    w.write_datetime_attribute("c", UNIX_EPOCH + Duration::new(1_700_000_000, 120_000_000))?;
+    w.write_datetime_attribute("d", UNIX_EPOCH - Duration::from_millis(1))?;
+    text_eq!(
+        w.end_document()?,
+        "<event a=\"1970-01-01T00:00:00Z\" b=\"2000-02-29T00:00:00Z\" \
+         c=\"2023-11-14T22:13:20.12Z\" d=\"1969-12-31T23:59:59.999Z\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_percentage_attribute_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("stop")?;
+    w.write_percentage_attribute("a", 0.5)?;
+    w.write_percentage_attribute("b", 1.5)?;
+    w.write_percentage_attribute("c", -0.1)?;
+    w.write_percentage_attribute("d", 1.0 / 3.0)?;
+    w.write_percentage_attribute("e", std::f64::NAN)?;
+    w.write_percentage_attribute("f", -0.0)?;
+    text_eq!(
+        w.end_document()?,
+        "<stop a=\"50%\" b=\"100%\" c=\"0%\" d=\"33.33%\" e=\"0%\" f=\"0%\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn trim_trailing_whitespace_01() -> io::Result<()> {
+    let opt = Options {
+        trim_trailing_whitespace: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("p")?;
+    w.write_text("")?;
+    w.end_element()?;
+    w.start_element("text")?;
+    w.write_text("a \t\nb  ")?;
+    w.write_text_fmt(format_args!("c {}", ' '))?;
+    text_eq!(
+        w.end_document()?,
+        "<svg>
+    <p>
+
+    </p>
+    <text>
+        a
+b
+        c
+    </text>
+</svg>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn trim_trailing_whitespace_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("")?;
+    text_eq!(w.end_document()?, "<p>\n    \n</p>\n");
+    Ok(())
+}
+
+#[test]
+fn trim_trailing_whitespace_03() -> io::Result<()> {
+    let opt = Options {
+        trim_trailing_whitespace: true,
+        line_ending: xmlwriter::LineEnding::CrLf,
+        attributes_indent: xmlwriter::Indent::Spaces(4),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.write_attribute("id", "a")?;
+    w.start_element("text")?;
+    w.write_text("a  ")?;
+    text_eq!(
+        w.end_document()?,
+        "<svg\r\n    id=\"a\">\r\n    <text>\r\n        a\r\n    </text>\r\n</svg>\r\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn inline_text_01() -> io::Result<()> {
+    let opt = Options {
+        inline_text: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("html")?;
+    w.start_element("head")?;
+    w.start_element("title")?;
+    w.write_text("Hello")?;
+    w.end_element()?;
+    w.end_element()?;
+    w.start_element("p")?;
+    w.write_attribute("id", "p1")?;
+    w.write_text("Text")?;
+    w.start_element("b")?;
+    w.write_text("bold")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<html>
+    <head>
+        <title>Hello</title>
+    </head>
+    <p id="p1">Text
+        <b>bold</b>
+    </p>
+</html>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn inline_text_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        inline_text: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("div")?;
+    w.start_element("title")?;
+    w.write_text("Hello")?;
+    text_eq!(w.end_document()?, "<div><title>Hello</title></div>");
+    Ok(())
+}
+
+#[test]
+fn write_uri_attribute_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("a")?;
+    w.write_uri_attribute("href", "/my docs/a b.html?x=1&y=\"é\"#top")?;
+    w.write_uri_attribute("src", "a%20b")?;
+    text_eq!(
+        w.end_document()?,
+        "<a href=\"/my%20docs/a%20b.html?x=1&amp;y=%22%C3%A9%22#top\" src=\"a%20b\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_xml_lang_01() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("root")?;
+    for lang in &["en", "en-US", "zh-Hant-TW", "de-CH-1901", "x-private", ""] {
+        w.start_element("p")?;
+        w.write_xml_lang(lang)?;
+        w.end_element()?;
+    }
+    w.start_element("p")?;
+    w.write_xml_base("http://example.com/a%20b/")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<root>
+    <p xml:lang="en"/>
+    <p xml:lang="en-US"/>
+    <p xml:lang="zh-Hant-TW"/>
+    <p xml:lang="de-CH-1901"/>
+    <p xml:lang="x-private"/>
+    <p xml:lang=""/>
+    <p xml:base="http://example.com/a%20b/"/>
+</root>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_xml_lang_02() -> io::Result<()> {
+    let opt = Options {
+        strict: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    for lang in &[
+        "en_US",
+        "1en",
+        "en-",
+        "-en",
+        "en--US",
+        "toolongtag",
+        "en US",
+    ] {
+        let err = w.write_xml_lang(lang).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    let err = w.write_xml_base("a b").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    text_eq!(w.end_document()?, "<p/>\n");
+
+    // Not validated without strict.
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_xml_lang("en_US")?;
+    w.write_xml_base("a b")?;
+    text_eq!(
+        w.end_document()?,
+        "<p xml:lang=\"en_US\" xml:base=\"a%20b\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_append_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("div")?;
+    w.start_element("p")?;
+    w.write_text_append("text")?;
+    w.write_text_append(" & more ]]")?;
+    w.write_text_append(">")?;
+    w.end_element()?;
+    w.write_text_append("after")?;
+    w.start_element("p")?;
+    w.end_element()?;
+    w.write_text_append("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<div>
+    <p>
+        text &amp; more ]]&gt;
+    </p>
+    after
+    <p/>
+    text
+</div>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn indent_root_01() -> io::Result<()> {
+    let opt = Options {
+        indent_root: false,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.set_base_depth(2);
+    w.start_element("svg")?;
+    w.write_attribute("id", "svg1")?;
+    w.start_element("g")?;
+    w.start_element("rect")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg id="svg1">
+            <g>
+                <rect/>
+            </g>
+        </svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn indent_root_02() -> io::Result<()> {
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.set_base_depth(1);
+    w.start_element("svg")?;
+    w.write_attribute("id", "svg1")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        r#"    <svg
+      id="svg1">
+        text
+    </svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_attribute_as_01() -> io::Result<()> {
+    use std::borrow::Cow;
+    use xmlwriter::AsXmlAttr;
+
+    enum Align {
+        Start,
+        End,
+        Custom(String),
+    }
+
+    impl AsXmlAttr for Align {
+        fn as_xml_attr(&self) -> Cow<'_, str> {
+            match self {
+                Align::Start => Cow::Borrowed("start"),
+                Align::End => Cow::Borrowed("end"),
+                Align::Custom(s) => Cow::Owned(format!("custom({})", s)),
+            }
+        }
+    }
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("text")?;
+    w.write_attribute_as("a", &Align::Start)?;
+    w.write_attribute_as("b", &Align::End)?;
+    w.write_attribute_as("c", &Align::Custom("<x>".to_string()))?;
+    text_eq!(
+        w.end_document()?,
+        "<text a=\"start\" b=\"end\" c=\"custom(&lt;x&gt;)\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn string_writer_01() -> io::Result<()> {
+    let mut w = xmlwriter::StringXmlWriter::new(Options::default());
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "q\"")?;
+    w.start_element("text")?;
+    w.write_text("<&>•")?;
+    w.end_element()?;
+    w.write_comment("test")?;
+    assert_eq!(
+        w.end_document(),
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<svg id="q&quot;">
+    <text>
+        &lt;&amp;&gt;•
+    </text>
+    <!--test-->
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn string_writer_02() {
+    use std::io::Write;
+
+    let mut w = xmlwriter::StringXmlWriter::new(Options::default());
+    w.start_element("svg").unwrap();
+    let err = w
+        .write_attribute_raw("id", |w| w.write_all(b"\xFF"))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn write_raw_fmt_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_raw_fmt(format_args!("<!DOCTYPE {}>\n", "svg"))?;
+    w.start_element("svg")?;
+    w.write_raw_fmt(format_args!(" data-{}=\"<&>\"", 1))?;
+    text_eq!(w.end_document()?, "<!DOCTYPE svg>\n<svg data-1=\"<&>\"/>\n");
+    Ok(())
+}
+
+#[test]
+fn attribute_to_element_01() -> io::Result<()> {
+    let opt = Options {
+        attribute_to_element_if: Some(|_, value| value.len() > 10),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("items")?;
+    w.start_element("item")?;
+    w.write_attribute("id", "1")?;
+    w.write_attribute("description", "A long <description>")?;
+    w.write_attribute_fmt("size", format_args!("{}", 5))?;
+    w.end_element()?;
+    w.start_element("item")?;
+    w.write_attribute("note", "Another long note")?;
+    w.start_element("child")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<items>
+    <item id="1" size="5">
+        <description>
+            A long &lt;description&gt;
+        </description>
+    </item>
+    <item>
+        <note>
+            Another long note
+        </note>
+        <child/>
+    </item>
+</items>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_auto_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("root")?;
+    w.start_element("p")?;
+    w.write_text_auto("a<b")?;
+    w.end_element()?;
+    w.start_element("p")?;
+    w.write_text_auto("plain")?;
+    w.end_element()?;
+    w.start_element("p")?;
+    w.write_text_auto("a]]>b")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<root>
+    <p><![CDATA[
+        a<b
+    ]]></p>
+    <p>
+        plain
+    </p>
+    <p>
+        a]]&gt;b
+    </p>
+</root>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn writer_error_01() {
+    // Fails only on a specific chunk, so we can reach the formatting code.
+    struct FailingWriter;
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf == b"boom" {
+                Err(io::Error::new(io::ErrorKind::Other, "disk is full"))
+            } else {
+                Ok(buf.len())
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut w = XmlWriter::new(FailingWriter, Options::default());
+    w.start_element("p").expect("no error expected here!");
+    let err = w
+        .write_text_fmt(format_args!("{}", "boom"))
+        .expect_err("the writer must fail");
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert_eq!(err.to_string(), "disk is full");
+}
+
+#[test]
+fn write_attribute_lazy_01() -> io::Result<()> {
+    let mut calls = 0;
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("rect")?;
+    w.write_attribute_lazy("id", || {
+        calls += 1;
+        "a&b".to_string()
+    })?;
+    w.write_attribute_lazy("class", || {
+        calls += 1;
+        String::new()
+    })?;
+    text_eq!(w.end_document()?, "<rect id=\"a&amp;b\"/>\n");
+    // The closure decides whether the attribute is skipped, so it's always called.
+    assert_eq!(calls, 2);
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "must be called after start_element()")]
+fn write_attribute_lazy_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_attribute_lazy("id", || panic!("the closure must not be called"))
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn xml_version_01() -> io::Result<()> {
+    let opt = Options {
+        xml_version: xmlwriter::XmlVersion::Xml11,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("p")?;
+    w.write_attribute("a", "\u{1B}[0m\t\u{85}\u{86}")?;
+    w.write_text("a\u{1}b\u{9F}\n")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.1\" encoding=\"UTF-8\" standalone=\"no\"?>
+<p a=\"&#x1B;[0m\t\u{85}&#x86;\">
+    a&#x01;b&#x9F;\n
+</p>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn xml_version_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p").expect("no error expected here!");
+    w.write_text("\u{7F}\u{9F}")
+        .expect("no error expected here!");
+    let err = w.write_text("a\u{1}b").expect_err("must be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn xml_version_03() {
+    let opt = Options {
+        xml_version: xmlwriter::XmlVersion::Xml11,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p").expect("no error expected here!");
+    let err = w.write_attribute("a", "\0").expect_err("must be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn xml_version_04() {
+    // Form feed and vertical tab are not allowed in XML 1.0.
+    for s in &["a\u{B}b", "a\u{C}b"] {
+        let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+        w.start_element("p").expect("no error expected here!");
+        let err = w.write_attribute("a", s).expect_err("must be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+        w.start_element("p").expect("no error expected here!");
+        let err = w.write_text(s).expect_err("must be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn xml_version_05() -> io::Result<()> {
+    let opt = Options {
+        xml_version: xmlwriter::XmlVersion::Xml11,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_attribute("a", "\u{B}\u{C}")?;
+    w.write_text("a\u{C}b\u{B}c")?;
+    text_eq!(
+        w.end_document()?,
+        "<p a=\"&#x0B;&#x0C;\">\n    a&#x0C;b&#x0B;c\n</p>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_base64_element_01() -> io::Result<()> {
+    let data: Vec<u8> = (0..100).collect();
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("data")?;
+    w.write_base64_element("a", b"")?;
+    w.write_base64_element("b", b"f")?;
+    w.write_base64_element("c", b"fo")?;
+    w.write_base64_element("d", b"foobar")?;
+    w.write_base64_element("e", &data)?;
+    w.write_base64_element("f", &[0xFB, 0xFF])?;
+    text_eq!(
+        w.end_document()?,
+        "<data>
+    <a/>
+    <b>
+        Zg==
+    </b>
+    <c>
+        Zm8=
+    </c>
+    <d>
+        Zm9vYmFy
+    </d>
+    <e>
+        AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5fYGFiYw==
+    </e>
+    <f>
+        +/8=
+    </f>
+</data>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_base64_element_02() -> io::Result<()> {
+    let opt = Options {
+        base64_wrap_width: Some(10),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("data")?;
+    w.write_base64_element("a", b"foobar")?;
+    w.write_base64_element("b", b"foobarbaz")?;
+    w.write_base64_element("c", b"foobarbazqux")?;
+    text_eq!(
+        w.end_document()?,
+        "<data>
+    <a>
+        Zm9vYmFy
+    </a>
+    <b>
+        Zm9vYmFy
+        YmF6
+    </b>
+    <c>
+        Zm9vYmFy
+        YmF6cXV4
+    </c>
+</data>
+"
+    );
+
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        base64_wrap_width: Some(0),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_base64_element("a", b"foobar")?;
+    text_eq!(w.end_document()?, "<a>Zm9v\nYmFy</a>");
+    Ok(())
+}
+
+#[test]
+fn write_conditional_comment_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("html")?;
+    w.start_element("head")?;
+    w.write_conditional_comment("IE", |w| {
+        w.start_element("link")?;
+        w.write_attribute("rel", "stylesheet")?;
+        w.write_attribute("href", "ie.css")?;
+        Ok(())
+    })?;
+    w.start_element("title")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<html>
+    <head>
+        <!--[if IE]>
+        <link rel="stylesheet" href="ie.css"/>
+        <![endif]-->
+        <title/>
+    </head>
+</html>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "invalid conditional comment condition")]
+fn write_conditional_comment_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.write_conditional_comment("IE]>", |_| Ok(()))
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn attribute_schema_order_01() -> io::Result<()> {
+    use std::io::Write;
+
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.set_attribute_schema_order(&["id", "width", "height"]);
+    w.start_element("svg")?;
+    w.write_attribute("height", &20)?;
+    w.write_attribute("xmlns", "http://www.w3.org/2000/svg")?;
+    w.write_attribute_raw_checked("width", |w| w.write_all(b"10"))?;
+    w.write_attribute("id", "svg1")?;
+    w.write_attribute("class", "a")?;
+    w.start_element("rect")?;
+    w.write_attribute("height", &5)?;
+    w.write_attribute("id", "rect1")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg
+  id="svg1"
+  width="10"
+  height="20"
+  xmlns="http://www.w3.org/2000/svg"
+  class="a">
+    <rect
+      id="rect1"
+      height="5"/>
+</svg>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn escape_unicode_line_breaks_01() -> io::Result<()> {
+    let opt = Options {
+        escape_unicode_line_breaks: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_attribute("title", "a\u{2029}b")?;
+    w.write_text("a\u{2028}b\u{85}c\u{2027}")?;
+    text_eq!(
+        w.end_document()?,
+        "<p title=\"a&#x2029;b\">\n    a&#x2028;b&#x85;c\u{2027}\n</p>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn escape_unicode_line_breaks_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_text("a\u{2028}b\u{85}c")?;
+    text_eq!(w.end_document()?, "<p>\n    a\u{2028}b\u{85}c\n</p>\n");
+    Ok(())
+}
+
+fn write_pretty_test_document<W: std::io::Write>(w: &mut XmlWriter<W>) -> io::Result<()> {
+    w.write_declaration()?;
+    w.write_comment("comment")?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "svg1")?;
+    w.start_element("g")?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.start_element("text")?;
+    w.write_text("text")?;
+    w.end_element()?;
+    w.start_element("style")?;
+    w.set_preserve_whitespaces(true);
+    w.start_element("tspan")?;
+    w.write_text("a")?;
+    w.end_element()?;
+    w.end_element()?;
+    w.set_preserve_whitespaces(false);
+    w.write_comment("comment")?;
+    Ok(())
+}
+
+#[test]
+fn end_document_pretty_01() -> io::Result<()> {
+    for &indent in &[
+        xmlwriter::Indent::Spaces(2),
+        xmlwriter::Indent::Tabs,
+        xmlwriter::Indent::None,
+    ] {
+        let opt = Options {
+            indent: xmlwriter::Indent::None,
+            record_node_offsets: true,
+            ..Options::default()
+        };
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        write_pretty_test_document(&mut w)?;
+        let compact = w.end_document_pretty(indent)?;
+
+        let opt = Options {
+            indent,
+            ..Options::default()
+        };
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        write_pretty_test_document(&mut w)?;
+        let pretty = w.end_document()?;
+
+        assert_eq!(
+            std::str::from_utf8(&compact).unwrap(),
+            std::str::from_utf8(&pretty).unwrap()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn end_document_pretty_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        record_node_offsets: true,
+        ..Options::default()
+    };
+    let mut w = XmlWriter::new(b"<!-- head -->".to_vec(), opt);
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    text_eq!(
+        w.end_document_pretty(xmlwriter::Indent::Spaces(4))?,
+        "<!-- head --><svg>\n    <rect/>\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "end_document_pretty requires Options::record_node_offsets")]
+fn end_document_pretty_03() {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+    let w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.end_document_pretty(xmlwriter::Indent::Spaces(4))
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn end_document_pretty_04() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        record_node_offsets: true,
+        ..Options::default()
+    };
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.write_text("text")?;
+    let data = w.swap_writer(Vec::new());
+    w.start_element("rect")?;
+    text_eq!(data, "<svg><g>text");
+    text_eq!(
+        w.end_document_pretty(xmlwriter::Indent::Spaces(2))?,
+        "\n    <rect/>\n  </g>\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn cancel_element_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.start_element("g")?;
+    w.cancel_element()?;
+    w.start_element("rect")?;
+    w.end_element()?;
+    w.start_element("g")?;
+    w.cancel_element()?;
+    w.start_element("circle")?;
+    text_eq!(
+        w.end_document()?,
+        "<svg>\n    <rect/>\n    <circle/>\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn cancel_element_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.write_attribute("id", "a")?;
+    w.start_element("g")?;
+    w.cancel_element()?;
+    w.write_attribute("class", "b")?;
+    text_eq!(w.end_document()?, "<svg id=\"a\" class=\"b\"/>\n");
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "the current element cannot be canceled")]
+fn cancel_element_03() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")
+        .expect("we'll panic before even returning a Result");
+    w.write_attribute("id", "a")
+        .expect("we'll panic before even returning a Result");
+    w.cancel_element()
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn element_scope_01() -> io::Result<()> {
+    let opt = Options {
+        namespace_decls_first: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.element_scope(
+        "svg",
+        |a| {
+            a.write_attribute("width", &100)?;
+            a.write_attribute_fmt("height", format_args!("{}", 50))?;
+            a.write_attribute("xmlns", "http://www.w3.org/2000/svg")
+        },
+        |w| {
+            w.element_scope("g", |_| Ok(()), |_| Ok(()))?;
+            w.element_scope(
+                "text",
+                |a| a.write_attribute_cow("x", "1".into()),
+                |w| {
+                    w.write_text("Hello")?;
+                    w.start_element("tspan")
+                },
+            )
+        },
+    )?;
+    w.element_scope("svg", |_| Ok(()), |_| Ok(()))?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" height="50" width="100">
+    <g/>
+    <text x="1">
+        Hello
+        <tspan/>
+    </text>
+</svg>
+<svg/>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "the closure closed elements it didn't open")]
+fn element_scope_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.element_scope("svg", |_| Ok(()), |w| w.end_element())
+        .expect("we'll panic before even returning a Result");
+}
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl std::fmt::Display for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let opt = Options {
+            indent: xmlwriter::Indent::None,
+            ..Options::default()
+        };
+
+        let mut w = XmlWriter::from_formatter(f, opt);
+        w.start_element("point").map_err(|_| std::fmt::Error)?;
+        w.write_attribute("x", &self.x)
+            .map_err(|_| std::fmt::Error)?;
+        w.write_attribute("y", &self.y)
+            .map_err(|_| std::fmt::Error)?;
+        w.end_document().map_err(|_| std::fmt::Error)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn from_formatter_01() {
+    let p = Point { x: 1, y: 2 };
+    assert_eq!(p.to_string(), "<point x=\"1\" y=\"2\"/>");
+    assert_eq!(format!("[{}]", p), "[<point x=\"1\" y=\"2\"/>]");
+}
+
+#[test]
+fn begin_attribute_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.write_attribute("id", "p1")?;
+    w.begin_attribute("style")?;
+    w.push_attribute_value("color:red")?;
+    w.push_attribute_value(&';')?;
+    w.push_attribute_value("font-weight:bold")?;
+    w.end_attribute()?;
+    w.write_attribute("title", "a\"b")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        "<p id=\"p1\" style=\"color:red;font-weight:bold\" title=\"a&quot;b\">\n    text\n</p>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn begin_attribute_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    w.begin_attribute("title")?;
+    w.push_attribute_value("<a & b>")?;
+    w.push_attribute_value("\"")?;
+    w.end_attribute()?;
+    text_eq!(
+        w.end_document()?,
+        "<p title=\"&lt;a &amp; b&gt;&quot;\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "attribute is not closed, call end_attribute() first")]
+fn begin_attribute_03() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p").unwrap();
+    w.begin_attribute("style").unwrap();
+    w.start_element("span")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+#[should_panic(expected = "must be called after begin_attribute()")]
+fn begin_attribute_04() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p").unwrap();
+    w.push_attribute_value("color:red")
+        .expect("we'll panic before even returning a Result");
+}
+
+#[test]
+fn space_before_self_close_01() -> io::Result<()> {
+    let cases = [
+        (false, false, "<br/>\n"),
+        (false, true, "<br />\n"),
+        (true, false, "<img src=\"a.png\"/>\n"),
+        (true, true, "<img src=\"a.png\" />\n"),
+    ];
+
+    for &(with_attributes, space, expected) in &cases {
+        let opt = Options {
+            space_before_self_close: space,
+            ..Options::default()
+        };
+
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        if with_attributes {
+            w.start_element("img")?;
+            w.write_attribute("src", "a.png")?;
+        } else {
+            w.start_element("br")?;
+        }
+        text_eq!(w.end_document()?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn space_before_self_close_02() -> io::Result<()> {
+    let opt = Options {
+        enable_self_closing: false,
+        space_before_self_close: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("img")?;
+    w.write_attribute("src", "a.png")?;
+    text_eq!(w.end_document()?, "<img src=\"a.png\">\n</img>\n");
+    Ok(())
+}
+
+#[test]
+fn write_attribute_chars_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("svg")?;
+    w.write_attribute_chars("title", "a < b & 'c'".chars().filter(|c| *c != ' '))?;
+    w.write_attribute_chars("id", None)?;
+    text_eq!(
+        w.end_document()?,
+        "<svg title=\"a&lt;b&amp;'c'\" id=\"\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_opt_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.start_element("span")?;
+    w.write_text_opt(None::<&str>)?;
+    w.end_element()?;
+    w.start_element("span")?;
+    w.write_text_opt(Some(&5))?;
+    w.end_element()?;
+    w.start_element("span")?;
+    w.write_text_opt(Some("a & b"))?;
+    text_eq!(
+        w.end_document()?,
+        "<p><span/><span>5</span><span>a &amp; b</span></p>"
+    );
+    Ok(())
+}
+
+#[test]
+fn indent_width_01() {
+    use xmlwriter::Indent;
+
+    assert_eq!(Indent::DEFAULT, Indent::Spaces(4));
+    assert_eq!(Options::default().indent, Indent::DEFAULT);
+    assert_eq!(Indent::spaces(2), Indent::Spaces(2));
+    assert_eq!(Indent::Spaces(2).width(), 2);
+    assert_eq!(Indent::Tabs.width(), 1);
+    assert_eq!(Indent::None.width(), 0);
+}
+
+fn write_error_list<W: std::io::Write>(w: &mut XmlWriter<W>, errors: &[&str]) -> io::Result<()> {
+    w.write_element_if_nonempty("errors", |w| {
+        for error in errors {
+            w.start_element("error")?;
+            w.write_attribute("message", error)?;
+            w.end_element()?;
+        }
+        Ok(!errors.is_empty())
+    })
+}
+
+#[test]
+fn write_element_if_nonempty_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("report")?;
+    write_error_list(&mut w, &[])?;
+    w.start_element("summary")?;
+    w.end_element()?;
+    write_error_list(&mut w, &["a", "b"])?;
+    text_eq!(
+        w.end_document()?,
+        r#"<report>
+    <summary/>
+    <errors>
+        <error message="a"/>
+        <error message="b"/>
+    </errors>
+</report>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_element_if_nonempty_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("report")?;
+    write_error_list(&mut w, &[])?;
+    // Nothing was written, so the returned flag is ignored.
+    w.write_element_if_nonempty("warnings", |_| Ok(true))?;
+    w.write_attribute("id", "r1")?;
+    text_eq!(w.end_document()?, "<report id=\"r1\"/>\n");
+    Ok(())
+}
+
+#[test]
+fn write_element_if_nonempty_03() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("report")?;
+    w.write_element_if_nonempty("section", |w| {
+        write_error_list(w, &[])?;
+        write_error_list(w, &["a"])?;
+        Ok(true)
+    })?;
+    w.write_element_if_nonempty("section", |w| {
+        write_error_list(w, &[])?;
+        Ok(true)
+    })?;
+    text_eq!(
+        w.end_document()?,
+        "<report><section><errors><error message=\"a\"/></errors></section></report>"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_element_if_nonempty_04() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("report")?;
+    let err = w
+        .write_element_if_nonempty("errors", |w| {
+            w.start_element("error")?;
+            w.start_element("details")?;
+            Err(io::Error::new(io::ErrorKind::Other, "failed"))
+        })
+        .expect_err("the closure error must be returned");
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    // The failed element is omitted and the writer is still usable.
+    w.write_attribute("id", "r1")?;
+    w.start_element("warnings")?;
+    text_eq!(
+        w.end_document()?,
+        "<report id=\"r1\">\n    <warnings/>\n</report>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn namespace_decls_first_01() -> io::Result<()> {
+    let opt = Options {
+        namespace_decls_first: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("root")?;
+    w.write_attribute("xmlns:a", "http://a")?;
+    w.write_attribute("id", "r1")?;
+    w.write_attribute("xmlns", "http://default")?;
+    w.write_attribute("class", "c")?;
+    w.start_element("child")?;
+    w.write_attribute("b", "2")?;
+    w.write_attribute("a:c", "3")?;
+    w.write_attribute("xmlns:z", "http://z")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<root xmlns="http://default" xmlns:a="http://a" class="c" id="r1">
+    <child xmlns:z="http://z" a:c="3" b="2"/>
+</root>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn options_validate_01() {
+    use xmlwriter::{Indent, OptionsError};
+
+    assert!(Options::default().validate().is_ok());
+
+    let opt = Options {
+        indent: Indent::None,
+        attributes_indent: Indent::Spaces(2),
+        ..Options::default()
+    };
+    assert_eq!(
+        opt.validate().unwrap_err(),
+        OptionsError::AttributesIndentWithoutIndent
+    );
+
+    let opt = Options {
+        record_node_offsets: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        opt.validate().unwrap_err(),
+        OptionsError::RecordNodeOffsetsWithIndent
+    );
+
+    let opt = Options {
+        enable_self_closing: false,
+        space_before_self_close: true,
+        ..Options::default()
+    };
+    let err = opt.validate().unwrap_err();
+    assert_eq!(err, OptionsError::SpaceBeforeSelfCloseWithoutSelfClosing);
+    assert_eq!(
+        err.to_string(),
+        "space_before_self_close requires enable_self_closing"
+    );
+}
+
+#[test]
+fn always_self_close_01() -> io::Result<()> {
+    let opt = Options {
+        enable_self_closing: false,
+        always_self_close: &["meta"],
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("head")?;
+    w.start_element("meta")?;
+    w.write_attribute("charset", "utf-8")?;
+    w.end_element()?;
+    w.start_element("title")?;
+    w.end_element()?;
+    w.start_element("meta")?;
+    w.start_element("link")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<head>
+    <meta charset="utf-8"/>
+    <title>
+    </title>
+    <meta>
+        <link>
+        </link>
+    </meta>
+</head>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn write_attribute_from_reader_01() -> io::Result<()> {
+    // Large enough to be read in several chunks, with multi-byte characters
+    // split between them.
+    let value = "a<b>&\"ж€😀".repeat(2000);
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("a")?;
+    w.write_attribute_from_reader("href", &mut value.as_bytes())?;
+    w.write_attribute("id", "a1")?;
+
+    let expected = format!(
+        "<a href=\"{}\" id=\"a1\"/>\n",
+        "a&lt;b&gt;&amp;&quot;ж€😀".repeat(2000)
+    );
+    text_eq!(w.end_document()?, expected.as_str());
+    Ok(())
+}
+
+#[test]
+fn write_attribute_from_reader_02() {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("a").expect("no error expected here!");
+    let err = w
+        .write_attribute_from_reader("href", &mut &b"abc\xE2\x82"[..])
+        .expect_err("must be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn omit_encoding_when_ascii_01() -> io::Result<()> {
+    let opt = Options {
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute("id", "svg1")?;
+    w.write_text("text")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.0\" standalone=\"no\"?>\n<svg id=\"svg1\">\n    text\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn omit_encoding_when_ascii_02() -> io::Result<()> {
+    let opt = Options {
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_text("текст")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n<svg>\n    текст\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn omit_encoding_when_ascii_03() -> io::Result<()> {
+    // Only UTF-8 can be omitted.
+    let opt = Options {
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration_with_encoding("ISO-8859-1")?;
+    w.start_element("svg")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.0\" encoding=\"ISO-8859-1\" standalone=\"no\"?>\n<svg/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn omit_encoding_when_ascii_04() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        record_node_offsets: true,
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.start_element("rect")?;
+    text_eq!(
+        w.end_document_pretty(xmlwriter::Indent::Spaces(2))?,
+        "<?xml version=\"1.0\" standalone=\"no\"?>\n<svg>\n  <rect/>\n</svg>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn omit_encoding_when_ascii_05() -> io::Result<()> {
+    // Raw data is not checked, so the encoding is kept.
+    let opt = Options {
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_attribute_raw("d", |writer| writer.write_all(b"M 10 20"))?;
+    w.write_attribute("id", "svg1")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n<svg d=\"M 10 20\" id=\"svg1\"/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn omit_encoding_when_ascii_06() -> io::Result<()> {
+    // Non-ASCII text of an omitted element doesn't count.
+    let opt = Options {
+        omit_encoding_when_ascii: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_declaration()?;
+    w.start_element("svg")?;
+    w.write_element_if_nonempty("title", |w| {
+        w.write_text("текст")?;
+        Ok(false)
+    })?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml version=\"1.0\" standalone=\"no\"?>\n<svg/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_html_text_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.write_html_text("a\n\n  b\t c")?;
+    w.start_element("b")?;
+    w.write_html_text(" \r\n\u{C}x &\u{A0}y ")?;
+    text_eq!(w.end_document()?, "<p>a b c<b>x &amp;\u{A0}y</b></p>");
+    Ok(())
+}
+
+#[test]
+fn cdata_content_elements_01() -> io::Result<()> {
+    let opt = Options {
+        cdata_content_elements: &["script", "style"],
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("html")?;
+    w.start_element("script")?;
+    w.write_text("if (a < b && c) {}")?;
+    w.end_element()?;
+    w.start_element("p")?;
+    w.write_text("a < b && c")?;
+    w.end_element()?;
+    w.start_element("style")?;
+    w.write_text("a[x]]>b {}")?;
     text_eq!(
         w.end_document()?,
-        r#"<rect
-  x="5">
+        r#"<html>
+    <script><![CDATA[
+        if (a < b && c) {}
+    ]]></script>
+    <p>
+        a &lt; b &amp;&amp; c
+    </p>
+    <style>
+        a[x]]&gt;b {}
+    </style>
+</html>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn close_to_depth_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    assert_eq!(w.depth(), 0);
+    w.start_element("a")?;
+    w.start_element("b")?;
+    w.start_element("c")?;
+    w.start_element("d")?;
+    assert_eq!(w.depth(), 4);
+    w.close_to_depth(1)?;
+    assert_eq!(w.depth(), 1);
+    w.close_to_depth(2)?;
+    assert_eq!(w.depth(), 1);
+    w.start_element("e")?;
+    text_eq!(w.end_document()?, "<a><b><c><d/></c></b><e/></a>");
+    Ok(())
+}
+
+#[test]
+fn write_xml_stylesheet_01() -> io::Result<()> {
+    let opt = Options {
+        use_single_quote: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.write_xml_stylesheet("it's \"a\".css", "text/css")?;
+    w.write_xml_model(
+        "book.rnc",
+        Some("application/relax-ng-compact-syntax"),
+        None,
+    )?;
+    w.start_element("svg")?;
+    text_eq!(
+        w.end_document()?,
+        "<?xml-stylesheet href='it&apos;s \"a\".css' type='text/css'?>
+<?xml-model href='book.rnc' type='application/relax-ng-compact-syntax'?>
+<svg/>
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn start_element_nowrap_01() -> io::Result<()> {
+    let opt = Options {
+        attributes_indent: xmlwriter::Indent::Spaces(2),
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.write_attribute("id", "svg1")?;
+    w.write_attribute("width", &10)?;
+    w.start_element_nowrap("a-very-long-element-name-that-would-look-worse-wrapped")?;
+    w.write_attribute("id", "e1")?;
+    w.write_attribute("class", "c")?;
+    w.end_element()?;
+    w.start_element("rect")?;
+    w.write_attribute("id", "rect1")?;
+    text_eq!(
+        w.end_document()?,
+        r#"<svg
+  id="svg1"
+  width="10">
+    <a-very-long-element-name-that-would-look-worse-wrapped id="e1" class="c"/>
     <rect
-      x="10"
-      y="15"/>
-</rect>
+      id="rect1"/>
+</svg>
 "#
     );
     Ok(())
 }
 
-// At some point I had used split_at() with a byte index but that does not work for multi-bytes
-// characters, so let's that to make sure it isn't reintroduced.
 #[test]
-fn multibytes_escaping() -> io::Result<()> {
+fn escape_attribute_value_01() {
+    use std::borrow::Cow;
+    use xmlwriter::escape_attribute_value;
+
+    // Same as write_attribute_05() and write_attribute_06().
+    assert_eq!(
+        escape_attribute_value("'''\"'\"\"'", true),
+        "&apos;&apos;&apos;\"&apos;\"\"&apos;"
+    );
+    assert_eq!(
+        escape_attribute_value("'''\"'\"\"'", false),
+        "'''&quot;'&quot;&quot;'"
+    );
+
+    assert_eq!(escape_attribute_value("a<b>&c", false), "a&lt;b&gt;&amp;c");
+    match escape_attribute_value("plain 'text'", false) {
+        Cow::Borrowed(s) => assert_eq!(s, "plain 'text'"),
+        Cow::Owned(_) => panic!("nothing to escape, so must be borrowed"),
+    }
+}
+
+#[test]
+fn escape_text_01() {
+    use std::borrow::Cow;
+    use xmlwriter::escape_text;
+
+    assert_eq!(escape_text("<a> & 'b' \"c\""), "&lt;a&gt; &amp; 'b' \"c\"");
+    match escape_text("text") {
+        Cow::Borrowed(s) => assert_eq!(s, "text"),
+        Cow::Owned(_) => panic!("nothing to escape, so must be borrowed"),
+    }
+}
+
+struct Coords {
+    x: i32,
+    y: i32,
+    label: Option<&'static str>,
+}
+
+impl xmlwriter::WriteAttributes for Coords {
+    fn write_attributes<W: std::io::Write>(&self, w: &mut XmlWriter<W>) -> io::Result<()> {
+        w.write_attribute("x", &self.x)?;
+        w.write_attribute("y", &self.y)?;
+        w.write_attribute("label", self.label)
+    }
+}
+
+#[test]
+fn start_element_with_01() -> io::Result<()> {
     let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
-    w.start_element("test")?;
-    w.write_attribute("foo", "aaa&bbb<ccc•&•>&•")?;
-    w.write_attribute_fmt("bar", format_args!("aaa&bbb<ccc{}&•>&•", '•'))?;
-    w.write_text("aaa&bbb<ccc•&•>&•")?;
-    w.write_text_fmt(format_args!("aaa&bbb<ccc{}&•>&•", '•'))?;
+    w.start_element("points")?;
+    w.start_element_with(
+        "point",
+        &Coords {
+            x: 1,
+            y: 2,
+            label: None,
+        },
+    )?;
+    w.end_element()?;
+    w.start_element_with(
+        "point",
+        &Coords {
+            x: 3,
+            y: 4,
+            label: Some("<end>"),
+        },
+    )?;
+    text_eq!(
+        w.end_document()?,
+        r#"<points>
+    <point x="1" y="2"/>
+    <point x="3" y="4" label="&lt;end&gt;"/>
+</points>
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn newline_after_open_tag_01() -> io::Result<()> {
+    let opt = Options {
+        inline_text: true,
+        newline_after_open_tag: true,
+        ..Options::default()
+    };
 
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("html")?;
+    w.start_element("title")?;
+    w.write_text("Title")?;
+    w.end_element()?;
+    w.start_element("br")?;
+    w.end_element()?;
+    w.start_element("script")?;
+    w.write_cdata_text("a < b")?;
+    w.end_element()?;
+    w.start_element("div")?;
+    w.start_element("p")?;
     text_eq!(
         w.end_document()?,
-        r#"<test foo="aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•" bar="aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•">
-    aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•
-    aaa&amp;bbb&lt;ccc•&amp;•&gt;&amp;•
-</test>
+        r#"<html>
+    <title>
+        Title
+    </title>
+    <br/>
+    <script>
+        <![CDATA[
+        a < b
+    ]]></script>
+    <div>
+        <p/>
+    </div>
+</html>
 "#
     );
     Ok(())
 }
 
 #[test]
-fn disabled_self_close() -> io::Result<()> {
-    let opts = Options {
-        enable_self_closing: false,
+fn write_numeric_text_01() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
         ..Options::default()
     };
-    let mut w = XmlWriter::new(Vec::<u8>::new(), opts);
-    w.start_element("empty1")?;
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("svg")?;
+    w.start_element("points")?;
+    w.write_numeric_text(&[1.0, 2.5, 3.333333], 2)?;
     w.end_element()?;
-    w.start_element("wrapper")?;
-    w.start_element("empty2")?;
+    w.start_element("points")?;
+    w.write_numeric_text(&[-0.001, 100.0, -1.25, 0.5], 0)?;
+    w.end_element()?;
+    w.start_element("points")?;
+    w.write_numeric_text(&[], 2)?;
+    text_eq!(
+        w.end_document()?,
+        "<svg><points>1 2.5 3.33</points><points>0 100 -1 0</points><points></points></svg>"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_numeric_text_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("points")?;
+    for values in &[[1.0, std::f64::NAN], [1.0, std::f64::INFINITY]] {
+        let err = w
+            .write_numeric_text(values, 2)
+            .expect_err("non-finite values must be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    text_eq!(w.end_document()?, "<points/>");
+    Ok(())
+}
+
+#[test]
+fn self_close_marker_01() -> io::Result<()> {
+    let opt = Options {
+        self_close_marker: " />",
+        ..Options::default()
+    }
+    .validate()
+    .expect("valid options");
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    w.start_element("br")?;
+    w.end_element()?;
+    w.start_element("img")?;
+    w.write_attribute("src", "a.png")?;
+    text_eq!(
+        w.end_document()?,
+        "<p>\n    <br />\n    <img src=\"a.png\" />\n</p>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn self_close_marker_02() {
+    use xmlwriter::OptionsError;
+
+    for marker in &["", ">", "/", " >", "/><"] {
+        let opt = Options {
+            self_close_marker: marker,
+            ..Options::default()
+        };
+        assert_eq!(
+            opt.validate().unwrap_err(),
+            OptionsError::InvalidSelfCloseMarker
+        );
+    }
+}
+
+#[test]
+fn self_close_marker_03() -> io::Result<()> {
+    let cases = [
+        ("/>", "<br />\n"),
+        (" />", "<br />\n"),
+        ("  />", "<br  />\n"),
+    ];
+
+    for &(marker, expected) in &cases {
+        let opt = Options {
+            space_before_self_close: true,
+            self_close_marker: marker,
+            ..Options::default()
+        };
+
+        let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+        w.start_element("br")?;
+        text_eq!(w.end_document()?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn end_document_with_stats_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("a")?;
+    w.start_element("b")?;
+    w.start_element("c")?;
     w.end_element()?;
+    let (data, closed) = w.end_document_with_stats()?;
+    assert_eq!(closed, 2);
+    text_eq!(data, "<a>\n    <b>\n        <c/>\n    </b>\n</a>\n");
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("a")?;
     w.end_element()?;
+    let (_, closed) = w.end_document_with_stats()?;
+    assert_eq!(closed, 0);
+    Ok(())
+}
+
+fn write_html_mode_test_document(opt: Options) -> io::Result<Vec<u8>> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("a")?;
+    w.write_attribute("title", "a < b > c & 'd' \"e\"")?;
+    w.write_text("a < b > c & 'd' \"e\"")?;
+    w.end_document()
+}
+
+#[test]
+fn html_mode_01() -> io::Result<()> {
+    text_eq!(
+        write_html_mode_test_document(Options::default())?,
+        "<a title=\"a &lt; b &gt; c &amp; 'd' &quot;e&quot;\">\n    a &lt; b &gt; c &amp; 'd' \"e\"\n</a>\n"
+    );
+
+    let opt = Options {
+        html_mode: true,
+        ..Options::default()
+    };
+    text_eq!(
+        write_html_mode_test_document(opt)?,
+        "<a title=\"a < b > c &amp; 'd' &quot;e&quot;\">\n    a &lt; b &gt; c &amp; 'd' \"e\"\n</a>\n"
+    );
+
+    let opt = Options {
+        html_mode: true,
+        use_single_quote: true,
+        ..Options::default()
+    };
+    text_eq!(
+        write_html_mode_test_document(opt)?,
+        "<a title='a < b > c &amp; &apos;d&apos; \"e\"'>\n    a &lt; b &gt; c &amp; 'd' \"e\"\n</a>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn html_mode_02() -> io::Result<()> {
+    let opt = Options {
+        html_mode: true,
+        skip_text_gt_escaping: true,
+        ..Options::default()
+    }
+    .validate()
+    .expect("valid options");
+    text_eq!(
+        write_html_mode_test_document(opt)?,
+        "<a title=\"a < b > c &amp; 'd' &quot;e&quot;\">\n    a &lt; b > c &amp; 'd' \"e\"\n</a>\n"
+    );
+
+    // Ignored in the XML mode.
+    let opt = Options {
+        skip_text_gt_escaping: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        opt.validate().unwrap_err(),
+        xmlwriter::OptionsError::SkipTextGtEscapingWithoutHtmlMode
+    );
+    text_eq!(
+        write_html_mode_test_document(opt)?,
+        "<a title=\"a &lt; b &gt; c &amp; 'd' &quot;e&quot;\">\n    a &lt; b &gt; c &amp; 'd' \"e\"\n</a>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_counted_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("p")?;
+    let count = w.write_text_counted("<a> & \"b\" ж")?;
+    let count2 = w.write_text_counted(&5)?;
+    let data = w.end_document()?;
+    text_eq!(
+        data.clone(),
+        "<p>\n    &lt;a&gt; &amp; \"b\" ж\n    5\n</p>\n"
+    );
+    // Everything except `<p` and `\n</p>\n`.
+    assert_eq!(count + count2, data.len() - 2 - 6);
+    assert_eq!(count2, "\n    5".len());
+    Ok(())
+}
+
+#[test]
+fn write_text_counted_02() -> io::Result<()> {
+    let opt = Options {
+        indent: xmlwriter::Indent::None,
+        trim_trailing_whitespace: true,
+        ..Options::default()
+    };
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("p")?;
+    // The trailing spaces are held back.
+    assert_eq!(w.write_text_counted("a  ")?, ">a".len());
+    // And written out before the next text.
+    assert_eq!(w.write_text_counted("b \n")?, "  b\n".len());
+    assert_eq!(w.write_text_counted("c")?, "c".len());
+    text_eq!(w.end_document()?, "<p>a  b\nc</p>");
+    Ok(())
+}
 
+#[test]
+fn write_text_chunks_01() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("data")?;
+    let chunks = vec!["a,b\n", "1,<2>\n", "3,&4"];
+    w.write_text_chunks(chunks.into_iter().map(|s| Ok(s.to_string())))?;
     text_eq!(
         w.end_document()?,
-        r#"<empty1>
-</empty1>
-<wrapper>
-    <empty2>
-    </empty2>
-</wrapper>
-"#
+        "<data>\n    a,b\n1,&lt;2&gt;\n3,&amp;4\n</data>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn write_text_chunks_02() -> io::Result<()> {
+    let mut w = XmlWriter::new(Vec::<u8>::new(), Options::default());
+    w.start_element("data")?;
+    let chunks = vec![
+        Ok("a".to_string()),
+        Err(io::Error::new(io::ErrorKind::Other, "source error")),
+        Ok("c".to_string()),
+    ];
+    let err = w.write_text_chunks(chunks).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert_eq!(err.to_string(), "source error");
+    text_eq!(w.end_document()?, "<data>\n    a\n</data>\n");
+    Ok(())
+}
+
+#[test]
+fn minimize_empty_attributes_01() -> io::Result<()> {
+    let opt = Options {
+        html_mode: true,
+        minimize_empty_attributes: true,
+        ..Options::default()
+    };
+
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("input")?;
+    w.write_attribute("type", "checkbox")?;
+    w.write_attribute("checked", "")?;
+    w.write_attribute_cow("disabled", "".into())?;
+    w.write_attribute_fmt("value", format_args!(""))?;
+    text_eq!(
+        w.end_document()?,
+        "<input type=\"checkbox\" checked disabled value/>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn minimize_empty_attributes_02() -> io::Result<()> {
+    // Ignored in the XML mode.
+    let opt = Options {
+        minimize_empty_attributes: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        opt.validate().unwrap_err(),
+        xmlwriter::OptionsError::MinimizeEmptyAttributesWithoutHtmlMode
     );
 
+    let mut w = XmlWriter::new(Vec::<u8>::new(), opt);
+    w.start_element("input")?;
+    w.write_attribute("checked", "")?;
+    text_eq!(w.end_document()?, "<input checked=\"\"/>\n");
     Ok(())
 }